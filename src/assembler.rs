@@ -0,0 +1,833 @@
+use crate::disassembler::Instruction;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Ensamblador con la misma sintaxis que produce el desensamblador:
+//
+//     SPEED EQU 4          ; constantes (también NAME = expr)
+//     loop:                ; etiquetas
+//         LD V0, SPEED * 2 ; expresiones: + - * / % & | ^ << >> ~ ( ) y $
+//         JP loop
+//     sprite:
+//         DB 0x3C, 0b01111110, "text"
+//         DW 0x1234
+//         ORG 0x300        ; avanza rellenando con ceros
+//         INCLUDE "other.asm"
+//
+// Las mnemónicas y los registros no distinguen mayúsculas, las etiquetas sí.
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{file}:{line}:{column}: {message}")]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+// el programa tiene que entrar en la memoria de XO-CHIP
+const MEMORY_SIZE: i64 = 0x10000;
+const MAX_EXPR_DEPTH: usize = 64;
+
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_lines(source, "<input>", Path::new("."), 0, &mut lines)?;
+    Assembler::new(origin).run(&lines)
+}
+
+pub fn assemble_file(path: &Path, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let source = std::fs::read_to_string(path).map_err(|e| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+
+    let mut lines = Vec::new();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    read_lines(&source, &path.display().to_string(), dir, 0, &mut lines)?;
+    Assembler::new(origin).run(&lines)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pos {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Pos {
+    fn at(&self, column: usize) -> Pos {
+        Pos { column, ..self.clone() }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+struct Line {
+    pos: Pos,
+    tokens: Vec<Token>,
+}
+
+// lee el código expandiendo los INCLUDE, que son relativos al archivo que los incluye
+fn read_lines(
+    source: &str,
+    file: &str,
+    dir: &Path,
+    depth: usize,
+    lines: &mut Vec<Line>,
+) -> Result<(), AsmError> {
+    for (i, text) in source.lines().enumerate() {
+        let pos = Pos { file: file.to_string(), line: i + 1, column: 1 };
+        let tokens = tokenize(text, &pos)?;
+
+        let include = match tokens.as_slice() {
+            [Token { kind: TokenKind::Ident(name), .. }, rest @ ..]
+                if name.eq_ignore_ascii_case("include") => Some(rest),
+            _ => None,
+        };
+
+        let Some(rest) = include else {
+            lines.push(Line { pos, tokens });
+            continue;
+        };
+
+        let path = match rest {
+            [Token { kind: TokenKind::Str(path), .. }] => dir.join(path),
+            _ => return Err(pos.error("INCLUDE expects a file name in quotes")),
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(pos.error("too many nested includes"));
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| pos.error(format!("{}: {}", path.display(), e)))?;
+        let dir: PathBuf = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        read_lines(&source, &path.display().to_string(), &dir, depth + 1, lines)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+const SYMBOLS: [&str; 18] = [
+    "<<", ">>", ",", ":", "(", ")", "[", "]", "+", "-", "*", "/", "%", "&", "|", "^", "~", "=",
+];
+
+fn tokenize(text: &str, pos: &Pos) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            tokens.push(Token { kind: TokenKind::Ident(ident), column });
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            let lower = literal.to_ascii_lowercase();
+
+            let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(bin) = lower.strip_prefix("0b") {
+                i64::from_str_radix(bin, 2)
+            } else {
+                lower.parse()
+            };
+            let number = parsed
+                .map_err(|_| pos.at(column).error(format!("invalid number '{}'", literal)))?;
+            tokens.push(Token { kind: TokenKind::Number(number), column });
+            continue;
+        }
+
+        if c == '"' {
+            let mut string = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(pos.at(column).error("unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some('0') => '\0',
+                            Some(&c @ ('\\' | '"')) => c,
+                            _ => return Err(pos.at(i + 1).error("invalid escape in string")),
+                        };
+                        string.push(escaped);
+                        i += 2;
+                    },
+                    Some(&c) => {
+                        string.push(c);
+                        i += 1;
+                    },
+                }
+            }
+            i += 1;
+            tokens.push(Token { kind: TokenKind::Str(string), column });
+            continue;
+        }
+
+        // $ es la dirección actual
+        if c == '$' {
+            tokens.push(Token { kind: TokenKind::Ident("$".to_string()), column });
+            i += 1;
+            continue;
+        }
+
+        let rest: String = chars[i..].iter().take(2).collect();
+        match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            Some(symbol) => {
+                tokens.push(Token { kind: TokenKind::Symbol(symbol), column });
+                i += symbol.len();
+            },
+            None => return Err(pos.at(column).error(format!("unexpected character '{}'", c))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Symbol(String, Pos),
+    // la posición es la del operador
+    Unary(&'static str, Box<Expr>, Pos),
+    Binary(&'static str, Box<Expr>, Box<Expr>, Pos),
+}
+
+#[derive(Debug, Clone)]
+enum OperandKind {
+    Register(u8),
+    Index,
+    IndexIndirect,
+    Delay,
+    Sound,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long(Expr),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Operand {
+    kind: OperandKind,
+    pos: Pos,
+}
+
+enum DataItem {
+    Value(Expr, Pos),
+    Bytes(Vec<u8>),
+}
+
+enum Statement {
+    Instruction { mnemonic: String, operands: Vec<Operand>, pos: Pos },
+    Bytes(Vec<DataItem>),
+    Words(Vec<(Expr, Pos)>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => {
+                let long = operands.iter().any(|o| matches!(o.kind, OperandKind::Long(_)));
+                if long { 4 } else { 2 }
+            },
+            Statement::Bytes(items) => items
+                .iter()
+                .map(|item| match item {
+                    DataItem::Value(..) => 1,
+                    DataItem::Bytes(bytes) => bytes.len(),
+                })
+                .sum(),
+            Statement::Words(words) => 2 * words.len(),
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    i: usize,
+    pos: &'a Pos,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a TokenKind> {
+        self.tokens.get(self.i).map(|t| &t.kind)
+    }
+
+    // columna del token actual (o del final de la línea)
+    fn here(&self) -> Pos {
+        let column = match self.tokens.get(self.i) {
+            Some(token) => token.column,
+            None => self.tokens.last().map_or(1, |t| t.column + 1),
+        };
+        self.pos.at(column)
+    }
+
+    fn next(&mut self) -> Option<&'a TokenKind> {
+        let token = self.peek();
+        self.i += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(TokenKind::Symbol(s)) if *s == symbol) {
+            self.i += 1;
+            return true;
+        }
+        false
+    }
+
+    fn at_end(&self) -> bool {
+        self.i >= self.tokens.len()
+    }
+
+    fn expect_end(&self) -> Result<(), AsmError> {
+        match self.at_end() {
+            true => Ok(()),
+            false => Err(self.here().error("unexpected text at end of line")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, AsmError> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let pos = self.here();
+            let op = match self.peek() {
+                Some(TokenKind::Symbol(s)) if LEVELS[level].contains(s) => *s,
+                _ => return Ok(left),
+            };
+            self.i += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), pos);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, AsmError> {
+        for op in ["-", "~", "+"] {
+            let pos = self.here();
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?), pos));
+            }
+        }
+
+        let pos = self.here();
+        match self.next() {
+            Some(TokenKind::Number(n)) => Ok(Expr::Number(*n)),
+            Some(TokenKind::Ident(name)) => Ok(Expr::Symbol(name.clone(), pos)),
+            Some(TokenKind::Symbol("(")) => {
+                let expr = self.expr()?;
+                if !self.eat(")") {
+                    return Err(self.here().error("expected ')'"));
+                }
+                Ok(expr)
+            },
+            _ => Err(pos.error("expected an expression")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let pos = self.here();
+
+        if self.eat("[") {
+            match self.next() {
+                Some(TokenKind::Ident(i)) if i.eq_ignore_ascii_case("i") => {},
+                _ => return Err(pos.error("expected [I]")),
+            }
+            if !self.eat("]") {
+                return Err(self.here().error("expected ']'"));
+            }
+            return Ok(Operand { kind: OperandKind::IndexIndirect, pos });
+        }
+
+        if let Some(TokenKind::Ident(name)) = self.peek() {
+            let upper = name.to_ascii_uppercase();
+            let keyword = match upper.as_str() {
+                "I" => Some(OperandKind::Index),
+                "DT" => Some(OperandKind::Delay),
+                "ST" => Some(OperandKind::Sound),
+                "K" => Some(OperandKind::Key),
+                "F" => Some(OperandKind::Font),
+                "HF" => Some(OperandKind::BigFont),
+                "B" => Some(OperandKind::Bcd),
+                "R" => Some(OperandKind::Flags),
+                _ => register(&upper).map(OperandKind::Register),
+            };
+
+            if let Some(kind) = keyword {
+                self.i += 1;
+                return Ok(Operand { kind, pos });
+            }
+            if upper == "LONG" {
+                self.i += 1;
+                return Ok(Operand { kind: OperandKind::Long(self.expr()?), pos });
+            }
+        }
+
+        Ok(Operand { kind: OperandKind::Value(self.expr()?), pos })
+    }
+
+    // lista separada por comas (puede estar vacía)
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, AsmError>) -> Result<Vec<T>, AsmError> {
+        let mut items = Vec::new();
+        if self.at_end() {
+            return Ok(items);
+        }
+
+        loop {
+            items.push(item(self)?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect_end()?;
+
+        Ok(items)
+    }
+}
+
+fn register(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix('V')?;
+    match digit.len() {
+        1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    register(&upper).is_some()
+        || ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "$"].contains(&upper.as_str())
+}
+
+struct Assembler {
+    origin: u16,
+    symbols: HashMap<String, Symbol>,
+}
+
+enum Symbol {
+    Label(i64),
+    Constant(Expr),
+}
+
+impl Assembler {
+    fn new(origin: u16) -> Assembler {
+        Assembler {
+            origin,
+            symbols: HashMap::new(),
+        }
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, pos: &Pos) -> Result<(), AsmError> {
+        if is_reserved(name) {
+            return Err(pos.error(format!("'{}' is a reserved name", name)));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(pos.error(format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn run(mut self, lines: &[Line]) -> Result<Vec<u8>, AsmError> {
+        // primera pasada: direcciones de las etiquetas
+        let mut statements = Vec::new();
+        let mut addr = self.origin as i64;
+
+        for line in lines {
+            let mut tokens = line.tokens.as_slice();
+
+            if let [Token { kind: TokenKind::Ident(name), column }, Token { kind: TokenKind::Symbol(":"), .. }, rest @ ..] = tokens {
+                self.define(name, Symbol::Label(addr), &line.pos.at(*column))?;
+                tokens = rest;
+            }
+
+            if tokens.is_empty() {
+                continue;
+            }
+            let pos = line.pos.at(tokens[0].column);
+
+            let mut parser = Parser { tokens, i: 0, pos: &line.pos };
+            let Some(TokenKind::Ident(name)) = parser.next() else {
+                return Err(pos.error("expected an instruction"));
+            };
+
+            // NAME EQU expr / NAME = expr
+            let is_constant = match parser.peek() {
+                Some(TokenKind::Ident(equ)) => equ.eq_ignore_ascii_case("equ"),
+                Some(TokenKind::Symbol("=")) => true,
+                _ => false,
+            };
+            if is_constant {
+                parser.i += 1;
+                let expr = parser.expr()?;
+                parser.expect_end()?;
+                self.define(name, Symbol::Constant(expr), &pos)?;
+                continue;
+            }
+
+            let statement = match name.to_ascii_uppercase().as_str() {
+                "ORG" => {
+                    let expr_pos = parser.here();
+                    let expr = parser.expr()?;
+                    parser.expect_end()?;
+
+                    let target = self.eval(&expr, addr, 0)?;
+                    if target < addr {
+                        return Err(expr_pos.error(format!(
+                            "ORG {:#X} is behind the current address {:#X}",
+                            target, addr
+                        )));
+                    }
+                    if target > MEMORY_SIZE {
+                        let message = format!("ORG {:#X} is past the end of memory", target);
+                        return Err(expr_pos.error(message));
+                    }
+                    addr = target;
+                    continue;
+                },
+                "DB" => Statement::Bytes(parser.list(|p| {
+                    let pos = p.here();
+                    if let Some(TokenKind::Str(s)) = p.peek() {
+                        p.i += 1;
+                        return Ok(DataItem::Bytes(s.as_bytes().to_vec()));
+                    }
+                    Ok(DataItem::Value(p.expr()?, pos))
+                })?),
+                "DW" => Statement::Words(parser.list(|p| {
+                    let pos = p.here();
+                    Ok((p.expr()?, pos))
+                })?),
+                mnemonic => Statement::Instruction {
+                    mnemonic: mnemonic.to_string(),
+                    operands: parser.list(|p| p.operand())?,
+                    pos,
+                },
+            };
+
+            let size = statement.size() as i64;
+            if addr + size > MEMORY_SIZE {
+                let pos = line.pos.at(tokens[0].column);
+                let message = format!("the program doesn't fit in {:#X} bytes", MEMORY_SIZE);
+                return Err(pos.error(message));
+            }
+            statements.push((addr, statement));
+            addr += size;
+        }
+
+        // segunda pasada: generar los bytes
+        let mut rom = vec![0; (addr - self.origin as i64).max(0) as usize];
+
+        for (addr, statement) in &statements {
+            let bytes = self.encode(statement, *addr)?;
+            let start = (*addr - self.origin as i64) as usize;
+            rom[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        Ok(rom)
+    }
+
+    fn eval(&self, expr: &Expr, addr: i64, depth: usize) -> Result<i64, AsmError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Symbol(name, pos) => {
+                if name == "$" {
+                    return Ok(addr);
+                }
+                if depth > MAX_EXPR_DEPTH {
+                    return Err(pos.error(format!("'{}' is defined in terms of itself", name)));
+                }
+                match self.symbols.get(name) {
+                    Some(Symbol::Label(value)) => Ok(*value),
+                    Some(Symbol::Constant(expr)) => self.eval(expr, addr, depth + 1),
+                    None => Err(pos.error(format!("undefined symbol '{}'", name))),
+                }
+            },
+            Expr::Unary(op, expr, pos) => {
+                let value = self.eval(expr, addr, depth)?;
+                match *op {
+                    "-" => value.checked_neg().ok_or_else(|| pos.error("overflow")),
+                    "~" => Ok(!value),
+                    _ => Ok(value),
+                }
+            },
+            Expr::Binary(op, left, right, pos) => {
+                let left = self.eval(left, addr, depth)?;
+                let right = self.eval(right, addr, depth)?;
+                if matches!(*op, "/" | "%") && right == 0 {
+                    return Err(pos.error("division by zero"));
+                }
+
+                // se corre de a lo sumo 63 y sin perder bits
+                let shift = u32::try_from(right).ok().filter(|&n| n < i64::BITS);
+                let result = match *op {
+                    "|" => Some(left | right),
+                    "^" => Some(left ^ right),
+                    "&" => Some(left & right),
+                    "<<" => shift.and_then(|n| Some(left << n).filter(|v| v >> n == left)),
+                    ">>" => shift.map(|n| left >> n),
+                    "+" => left.checked_add(right),
+                    "-" => left.checked_sub(right),
+                    "*" => left.checked_mul(right),
+                    "/" => left.checked_div(right),
+                    _ => left.checked_rem(right),
+                };
+                result.ok_or_else(|| pos.error("overflow"))
+            },
+        }
+    }
+
+    fn value(&self, expr: &Expr, pos: &Pos, addr: i64, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.eval(expr, addr, 0)?;
+        if value < min || value > max {
+            return Err(pos.error(format!("value {} out of range ({}..={})", value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn encode(&self, statement: &Statement, addr: i64) -> Result<Vec<u8>, AsmError> {
+        match statement {
+            Statement::Bytes(items) => {
+                let mut bytes = Vec::new();
+                for item in items {
+                    match item {
+                        DataItem::Value(expr, pos) => {
+                            bytes.push(self.value(expr, pos, addr, -128, 0xFF)? as u8);
+                        },
+                        DataItem::Bytes(b) => bytes.extend_from_slice(b),
+                    }
+                }
+                Ok(bytes)
+            },
+            Statement::Words(words) => {
+                let mut bytes = Vec::new();
+                for (expr, pos) in words {
+                    let word = self.value(expr, pos, addr, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+                Ok(bytes)
+            },
+            Statement::Instruction { mnemonic, operands, pos } => {
+                Ok(self.instruction(mnemonic, operands, pos, addr)?.to_bytes())
+            },
+        }
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        pos: &Pos,
+        addr: i64,
+    ) -> Result<Instruction, AsmError> {
+        use OperandKind::*;
+
+        let byte = |o: &Operand| match &o.kind {
+            Value(expr) => self.value(expr, &o.pos, addr, -128, 0xFF).map(|v| v as u8),
+            _ => Err(o.pos.error("expected a byte value")),
+        };
+        let address = |o: &Operand| match &o.kind {
+            Value(expr) => self.value(expr, &o.pos, addr, 0, 0xFFF).map(|v| v as u16),
+            _ => Err(o.pos.error("expected an address")),
+        };
+        let nibble = |o: &Operand| match &o.kind {
+            Value(expr) => self.value(expr, &o.pos, addr, 0, 0xF).map(|v| v as u8),
+            _ => Err(o.pos.error("expected a value between 0 and 15")),
+        };
+
+        let kinds: Vec<&OperandKind> = operands.iter().map(|o| &o.kind).collect();
+        let instruction = match (mnemonic, kinds.as_slice()) {
+            ("NOP", []) => Instruction::Nop,
+            ("CLS", []) => Instruction::Clear,
+            ("RET", []) => Instruction::Return,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("AUDIO", []) => Instruction::Audio,
+            ("SCD", [Value(_)]) => Instruction::ScrollDown(nibble(&operands[0])?),
+            ("SCU", [Value(_)]) => Instruction::ScrollUp(nibble(&operands[0])?),
+            ("PLANE", [Value(_)]) => Instruction::Planes(nibble(&operands[0])?),
+            ("JP", [Value(_)]) => Instruction::Jump(address(&operands[0])?),
+            ("JP", [Register(0), Value(_)]) => Instruction::JumpOffset(address(&operands[1])?),
+            ("CALL", [Value(_)]) => Instruction::Call(address(&operands[0])?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipEqual(*x, *y),
+            ("SE", [Register(x), Value(_)]) => Instruction::SkipEqualByte(*x, byte(&operands[1])?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipNotEqual(*x, *y),
+            ("SNE", [Register(x), Value(_)]) => Instruction::SkipNotEqualByte(*x, byte(&operands[1])?),
+            ("SAVE", [Register(x), Register(y)]) => Instruction::SaveRange(*x, *y),
+            ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Register(x), Register(y)]) => Instruction::Set(*x, *y),
+            ("LD", [Register(x), Value(_)]) => Instruction::SetByte(*x, byte(&operands[1])?),
+            ("LD", [Register(x), Delay]) => Instruction::GetDelay(*x),
+            ("LD", [Register(x), Key]) => Instruction::WaitKey(*x),
+            ("LD", [Register(x), IndexIndirect]) => Instruction::Restore(*x),
+            ("LD", [Register(x), Flags]) => Instruction::LoadFlags(*x),
+            ("LD", [Index, Value(_)]) => Instruction::SetIndex(address(&operands[1])?),
+            ("LD", [Index, Long(expr)]) => {
+                let value = self.value(expr, &operands[1].pos, addr, 0, 0xFFFF)?;
+                Instruction::SetIndexLong(value as u16)
+            },
+            ("LD", [Delay, Register(x)]) => Instruction::SetDelay(*x),
+            ("LD", [Sound, Register(x)]) => Instruction::SetSound(*x),
+            ("LD", [Font, Register(x)]) => Instruction::Font(*x),
+            ("LD", [BigFont, Register(x)]) => Instruction::BigFont(*x),
+            ("LD", [Bcd, Register(x)]) => Instruction::Bcd(*x),
+            ("LD", [IndexIndirect, Register(x)]) => Instruction::Store(*x),
+            ("LD", [Flags, Register(x)]) => Instruction::SaveFlags(*x),
+            ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+            ("ADD", [Register(x), Value(_)]) => Instruction::AddByte(*x, byte(&operands[1])?),
+            ("ADD", [Index, Register(x)]) => Instruction::AddIndex(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubReverse(*x, *y),
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Value(_)]) => Instruction::Random(*x, byte(&operands[1])?),
+            ("DRW", [Register(x), Register(y), Value(_)]) => {
+                Instruction::Draw(*x, *y, nibble(&operands[2])?)
+            },
+            ("SKP", [Register(x)]) => Instruction::SkipKey(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipNotKey(*x),
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            _ if is_mnemonic(mnemonic) => {
+                return Err(pos.error(format!("invalid operands for {}", mnemonic)))
+            },
+            _ => return Err(pos.error(format!("unknown instruction '{}'", mnemonic))),
+        };
+
+        Ok(instruction)
+    }
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    [
+        "NOP", "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "PLANE",
+        "JP", "CALL", "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN",
+        "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "PITCH",
+    ].contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        let e = assemble(source, 0x200).unwrap_err();
+        format!("{}:{}: {}", e.line, e.column, e.message)
+    }
+
+    #[test]
+    fn labels_constants_and_data() {
+        let source = "\
+            SPEED EQU 4\n\
+            start: LD V0, SPEED * 2 ; comentario\n\
+            JP end\n\
+            sprite: DB 0x3C, 0b1, \"hi\"\n\
+            end: LD I, sprite\n\
+            DW $\n";
+        assert_eq!(
+            assemble(source, 0x200).unwrap(),
+            [0x60, 0x08, 0x12, 0x08, 0x3c, 0x01, b'h', b'i', 0xa2, 0x04, 0x02, 0x0a]
+        );
+        assert_eq!(assemble("LD I, LONG 0x1234", 0x200).unwrap(), [0xf0, 0x00, 0x12, 0x34]);
+        assert_eq!(assemble("CLS\nORG 0x206\nRET", 0x200).unwrap(), [0, 0xe0, 0, 0, 0, 0, 0, 0xee]);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        assert_eq!(error("CLS\n  FOO V0"), "2:3: unknown instruction 'FOO'");
+        assert_eq!(error("LD V0, 0x100"), "1:8: value 256 out of range (-128..=255)");
+        assert_eq!(error("JP nowhere"), "1:4: undefined symbol 'nowhere'");
+        assert_eq!(error("a: CLS\na: CLS"), "2:1: 'a' is already defined");
+        assert_eq!(error("LD V0, 1 / 0"), "1:10: division by zero");
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert_eq!(error("DW 1 << 100"), "1:6: overflow");
+        assert_eq!(error("DW 0x4000000000000000 << 2"), "1:23: overflow");
+        assert_eq!(error("DW 0x7fffffffffffffff * 2"), "1:23: overflow");
+        assert_eq!(error("DW 0x7fffffffffffffff + 1"), "1:23: overflow");
+        assert_eq!(error("DW -0x7fffffffffffffff - 2"), "1:24: overflow");
+        assert_eq!(error("DW 1 >> -1"), "1:6: overflow");
+        assert_eq!(assemble("DW -1 << 4, 0xF0 >> 4", 0x200).unwrap(), [0xff, 0xf0, 0, 0x0f]);
+    }
+
+    #[test]
+    fn program_must_fit_in_memory() {
+        assert_eq!(error("ORG 0xFFFFFF"), "1:5: ORG 0xFFFFFF is past the end of memory");
+        assert_eq!(error("ORG 0xFFFE\nCLS\nCLS"), "3:1: the program doesn't fit in 0x10000 bytes");
+        assert_eq!(assemble("ORG 0xFFFE\nCLS", 0xFFFC).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,55 @@
+use chip_8::assembler::assemble_file;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage: chip8-asm <source> [-o <output.ch8>] [--origin ADDR]";
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+    let mut origin = 0x200;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            "--origin" => {
+                let addr = args.next().unwrap_or_default();
+                origin = match u16::from_str_radix(addr.trim_start_matches("0x"), 16) {
+                    Ok(addr) => addr,
+                    Err(_) => {
+                        eprintln!("invalid origin '{}'", addr);
+                        process::exit(2);
+                    }
+                };
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let Some(source) = source else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+    // por defecto, el mismo nombre con extensión .ch8
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+
+    let rom = match assemble_file(Path::new(&source), origin) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = std::fs::write(&output, &rom) {
+        eprintln!("{}: {}", output.display(), e);
+        process::exit(1);
+    }
+    println!("{}: {} bytes", output.display(), rom.len());
+}
@@ -0,0 +1,44 @@
+use chip_8::disassembler::disassemble_rom;
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: chip8-disasm <rom> [--origin ADDR]";
+
+fn main() {
+    let mut file = None;
+    let mut origin = 0x200;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => {
+                let addr = args.next().unwrap_or_default();
+                origin = match u16::from_str_radix(addr.trim_start_matches("0x"), 16) {
+                    Ok(addr) => addr,
+                    Err(_) => {
+                        eprintln!("invalid origin '{}'", addr);
+                        process::exit(2);
+                    }
+                };
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => file = Some(arg),
+        }
+    }
+
+    let Some(file) = file else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+
+    match std::fs::read(&file) {
+        Ok(rom) => print!("{}", disassemble_rom(&rom, origin)),
+        Err(e) => {
+            eprintln!("{}: {}", file, e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use chip_8::headless::{self, Format, KeyEvent};
+use chip_8::loader;
+use chip_8::{Chip8, QuirkProfile, Rng, DEFAULT_IPS, DEFAULT_LOAD_ADDRESS};
+use std::env;
+use std::io::Write;
+use std::process;
+
+const USAGE: &str = "\
+usage: chip8-headless <rom> [options]
+
+runs a ROM (or one inside a zip archive) without a window and writes the final
+screen to stdout
+
+options:
+  --frames N         frames to run at 60 Hz (default 600); stops earlier if
+                     the program exits, jumps to itself or waits for a key
+                     after the last key event
+  --ips N            instructions per second (default 480)
+  --quirks PROFILE   vip, chip48, schip, xochip or modern (default modern)
+  --seed N           seed for the random number generator (default 0)
+  --load-address A   where the ROM is loaded and starts, in hex (default 200)
+  --rom NAME         the ROM to run from a zip with several
+  --keys SCRIPT      key events, e.g. \"10:+5 40:-5 60:A\" (FRAME:+KEY press,
+                     FRAME:-KEY release, FRAME:KEY tap); @FILE reads a file
+  --format FORMAT    ascii, pbm, png or hash (default ascii)";
+
+fn fail(message: impl std::fmt::Display, code: i32) -> ! {
+    eprintln!("{}", message);
+    process::exit(code);
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    args.next().unwrap_or_else(|| fail(format!("{} expects a value\n\n{}", flag, USAGE), 2))
+}
+
+fn number<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let s = value(args, flag);
+    s.parse().unwrap_or_else(|_| fail(format!("invalid value '{}' for {}", s, flag), 2))
+}
+
+fn main() {
+    let mut file = None;
+    let mut rom = None;
+    let mut frames = 600;
+    let mut ips = DEFAULT_IPS;
+    let mut profile = QuirkProfile::Modern;
+    let mut seed = 0;
+    let mut load_address = DEFAULT_LOAD_ADDRESS;
+    let mut keys: Vec<KeyEvent> = Vec::new();
+    let mut format = Format::Ascii;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = number(&mut args, "--frames"),
+            "--ips" => ips = number(&mut args, "--ips"),
+            "--quirks" => profile = value(&mut args, "--quirks").parse().unwrap_or_else(|e| fail(e, 2)),
+            "--seed" => seed = number(&mut args, "--seed"),
+            "--load-address" => {
+                let s = value(&mut args, "--load-address");
+                load_address = u16::from_str_radix(s.trim_start_matches("0x"), 16)
+                    .unwrap_or_else(|_| {
+                        fail(format!("invalid value '{}' for --load-address", s), 2)
+                    });
+            },
+            "--rom" => rom = Some(value(&mut args, "--rom")),
+            "--keys" => {
+                let script = value(&mut args, "--keys");
+                let script = match script.strip_prefix('@') {
+                    Some(path) => std::fs::read_to_string(path)
+                        .unwrap_or_else(|e| fail(format!("{}: {}", path, e), 1)),
+                    None => script,
+                };
+                keys = headless::parse_keys(&script).unwrap_or_else(|e| fail(e, 2));
+            },
+            "--format" => format = value(&mut args, "--format").parse().unwrap_or_else(|e| fail(e, 2)),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with('-') => fail(format!("unknown option '{}'\n\n{}", arg, USAGE), 2),
+            _ => file = Some(arg),
+        }
+    }
+
+    let Some(file) = file else {
+        fail(USAGE, 2);
+    };
+
+    let mut chip8 = Chip8::with_rng(profile.into(), Rng::from_state(seed));
+    chip8.set_load_address(load_address);
+    let rom = loader::load(&file, rom.as_deref())
+        .unwrap_or_else(|e| fail(format!("{}: {}", file, e), 1));
+    if let Err(e) = chip8.load_rom_bytes(&rom.data) {
+        fail(format!("{}: {}", rom.name, e), 1);
+    }
+    chip8.set_instructions_per_second(ips);
+
+    let (ran, stop) = headless::run(&mut chip8, frames, &keys).unwrap_or_else(|e| fail(e, 1));
+    eprintln!("{}: {} after {} frames", rom.name, stop, ran);
+
+    let out = headless::dump(&chip8.screen, format);
+    if let Err(e) = std::io::stdout().write_all(&out) {
+        fail(e, 1);
+    }
+}
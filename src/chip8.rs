@@ -1,3 +1,55 @@
+use crate::disassembler::{disassemble, Instruction};
+use crate::font::{Font, BIG_FONT_SIZE, BIG_GLYPH_SIZE, FONT_SIZE, GLYPH_SIZE};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::state::{StateError, StateReader, StateWriter};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    #[error("stack overflow at {pc:#05x}")]
+    StackOverflow { pc: u16 },
+    #[error("stack underflow at {pc:#05x}")]
+    StackUnderflow { pc: u16 },
+    #[error("memory access out of bounds at {addr:#x}")]
+    MemoryOutOfBounds { addr: usize },
+    #[error("unknown opcode {opcode:04x} at {pc:#05x}")]
+    UnknownOpcode { pc: u16, opcode: u16 },
+    #[error("invalid key {key:#x} at {pc:#05x}")]
+    InvalidKey { pc: u16, key: u8 },
+}
+
+#[derive(Debug, Error)]
+pub enum RomError {
+    #[error("can't read the ROM: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("the ROM is empty")]
+    Empty,
+    #[error("the ROM has {size} bytes but only {max} fit from {address:#05x}")]
+    TooLarge { size: usize, max: usize, address: u16 },
+    #[error("this is a {0}, not a CHIP-8 ROM")]
+    NotARom(&'static str),
+}
+
+// acceso a memoria hecho por una instrucción (sin contar el fetch)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read(usize),
+    Write(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    // Fx0A esperando una tecla, se vuelve a ejecutar en el próximo step
+    WaitingForKey,
+    // Dxyn con el quirk display_wait, se dibuja después del próximo vblank
+    WaitingForVblank,
+    // 00FD, el programa terminó
+    Exited,
+}
+
 const STACK_SIZE: usize = 24;
 
 struct Stack {
@@ -13,100 +65,349 @@ impl Stack {
         }
     }
 
-    fn push(&mut self, val: u16) {
-        // que pasa si llego al final????
+    fn push(&mut self, val: u16) -> Option<()> {
+        *self.stack.get_mut(self.stack_pointer)? = val;
         self.stack_pointer += 1;
-        self.stack[self.stack_pointer] = val;
+        Some(())
     }
 
-    fn pop(&mut self) -> u16 {
-        // que pasa si ya estaba vacío el stack???
-        self.stack_pointer -= 1;
-        self.stack[self.stack_pointer + 1]
+    fn pop(&mut self) -> Option<u16> {
+        self.stack_pointer = self.stack_pointer.checked_sub(1)?;
+        Some(self.stack[self.stack_pointer])
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        for &val in &self.stack {
+            w.u16(val);
+        }
+        w.u8(self.stack_pointer as u8);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Stack, StateError> {
+        let mut stack = Stack::new();
+        for val in stack.stack.iter_mut() {
+            *val = r.u16()?;
+        }
+        stack.stack_pointer = r.u8()? as usize;
+
+        if stack.stack_pointer > STACK_SIZE {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        Ok(stack)
     }
 }
 
+// resolución normal (lores)
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+// resolución de SUPER-CHIP (hires)
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+// planos de XO-CHIP, cada pixel tiene un bit por plano
+pub const PLANES: usize = 4;
+// RGB de cada combinación de planos, para el frontend y los PNG
+pub const DEFAULT_PALETTE: [[u8; 3]; 1 << PLANES] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0x00],
+    [0x88, 0x00, 0x00],
+    [0x00, 0x88, 0x00],
+    [0x00, 0x00, 0x88],
+    [0x88, 0x88, 0x00],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0x88, 0x00, 0x88],
+    [0x00, 0x88, 0x88],
+];
+
+type Column = [u8; HIRES_SCREEN_HEIGHT];
 
 pub struct Screen {
-    // monocromático? es bool==????
-    screen: [[bool; SCREEN_HEIGHT]; SCREEN_WIDTH],
+    screen: [Column; HIRES_SCREEN_WIDTH],
+    hires: bool,
+    // máscara de planos seleccionados con Fn01
+    planes: u8,
 }
 
 impl Screen {
     fn new() -> Screen {
         Screen {
-            screen: [[false; SCREEN_HEIGHT]; SCREEN_WIDTH],
+            screen: [[0; HIRES_SCREEN_HEIGHT]; HIRES_SCREEN_WIDTH],
+            hires: false,
+            planes: 0x1,
         }
     }
 
-    fn set_pixel(&mut self, pos: (usize, usize), val: bool) {
+    fn set_pixel(&mut self, pos: (usize, usize), plane: u8, val: bool) {
         // validar x, y?
-        self.screen[pos.0][pos.1] = val;
+        let pixel = &mut self.screen[pos.0][pos.1];
+        match val {
+            true => *pixel |= plane,
+            false => *pixel &= !plane,
+        }
     }
 
+    // prendido en algún plano
     pub fn get_pixel(&self, pos:(usize, usize)) -> bool {
+        self.screen[pos.0][pos.1] != 0
+    }
+
+    // índice en la paleta (un bit por plano)
+    pub fn get_color(&self, pos: (usize, usize)) -> u8 {
         self.screen[pos.0][pos.1]
     }
+
+    fn is_set(&self, pos: (usize, usize), plane: u8) -> bool {
+        self.screen[pos.0][pos.1] & plane != 0
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // cambiar de resolución borra la pantalla (todos los planos)
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = [[0; HIRES_SCREEN_HEIGHT]; HIRES_SCREEN_WIDTH];
+    }
+
+    pub fn width(&self) -> usize {
+        match self.hires {
+            true => HIRES_SCREEN_WIDTH,
+            false => SCREEN_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.hires {
+            true => HIRES_SCREEN_HEIGHT,
+            false => SCREEN_HEIGHT,
+        }
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1) as u8;
+    }
+
+    // sólo borra los planos seleccionados
+    fn clear(&mut self) {
+        let keep = !self.planes;
+
+        for pixel in self.screen.iter_mut().flatten() {
+            *pixel &= keep;
+        }
+    }
+
+    // reemplaza los planos seleccionados de dst por los de src
+    fn blend(planes: u8, dst: u8, src: u8) -> u8 {
+        (dst & !planes) | (src & planes)
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let (height, planes) = (self.height(), self.planes);
+
+        for column in self.screen.iter_mut() {
+            for y in (0..height).rev() {
+                let src = if y >= n { column[y - n] } else { 0 };
+                column[y] = Screen::blend(planes, column[y], src);
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let (height, planes) = (self.height(), self.planes);
+
+        for column in self.screen.iter_mut() {
+            for y in 0..height {
+                let src = if y + n < height { column[y + n] } else { 0 };
+                column[y] = Screen::blend(planes, column[y], src);
+            }
+        }
+    }
+
+    fn scroll_right(&mut self, n: usize) {
+        for x in (0..self.width()).rev() {
+            let src = match x >= n {
+                true => self.screen[x - n],
+                false => [0; HIRES_SCREEN_HEIGHT],
+            };
+            self.blend_column(x, &src);
+        }
+    }
+
+    fn scroll_left(&mut self, n: usize) {
+        let width = self.width();
+
+        for x in 0..width {
+            let src = match x + n < width {
+                true => self.screen[x + n],
+                false => [0; HIRES_SCREEN_HEIGHT],
+            };
+            self.blend_column(x, &src);
+        }
+    }
+
+    fn blend_column(&mut self, x: usize, src: &Column) {
+        let planes = self.planes;
+
+        for (dst, &src) in self.screen[x].iter_mut().zip(src) {
+            *dst = Screen::blend(planes, *dst, src);
+        }
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        for column in &self.screen {
+            w.bytes(column);
+        }
+        w.bool(self.hires);
+        w.u8(self.planes);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Screen, StateError> {
+        let mut screen = Screen::new();
+        for column in screen.screen.iter_mut() {
+            *column = r.array()?;
+        }
+        screen.hires = r.bool()?;
+        screen.select_planes(r.u8()?);
+
+        Ok(screen)
+    }
 }
 
 const KEYS: usize = 16;
 
+// además del estado de cada tecla, qué cambió en el frame actual
 struct Keyboard {
     keys: [bool; KEYS],
+    just_pressed: [bool; KEYS],
+    just_released: [bool; KEYS],
 }
 
 impl Keyboard {
     fn new() -> Keyboard {
         Keyboard {
             keys: [false; KEYS],
+            just_pressed: [false; KEYS],
+            just_released: [false; KEYS],
         }
     }
 
     fn set_key(&mut self, key: u8, pressed: bool) {
-        self.keys[key as usize] = pressed;
+        let key = key as usize;
+        if self.keys[key] != pressed {
+            if pressed {
+                self.just_pressed[key] = true;
+            } else {
+                self.just_released[key] = true;
+            }
+        }
+        self.keys[key] = pressed;
     }
 
     fn is_pressed(&self, key: u8) -> bool {
         self.keys[key as usize]
     }
 
-    fn keys_pressed(&self) -> Vec<u8> {
-        self.keys
-            .iter()
-            .enumerate()
-            .filter(|(_, &k)| k)
-            .map(|(i, _)| i as u8)
-            .collect()
+    // en cada tick de 60 Hz
+    fn end_frame(&mut self) {
+        self.just_pressed = [false; KEYS];
+        self.just_released = [false; KEYS];
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        for keys in [&self.keys, &self.just_pressed, &self.just_released] {
+            for &key in keys {
+                w.bool(key);
+            }
+        }
+    }
+
+    fn load(r: &mut StateReader) -> Result<Keyboard, StateError> {
+        let mut keyboard = Keyboard::new();
+        for keys in [&mut keyboard.keys, &mut keyboard.just_pressed, &mut keyboard.just_released] {
+            for key in keys.iter_mut() {
+                *key = r.bool()?;
+            }
+        }
+        Ok(keyboard)
+    }
+
+    fn first(keys: &[bool; KEYS]) -> Option<u8> {
+        keys.iter().position(|&k| k).map(|i| i as u8)
+    }
+}
+
+// Fx0A
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    Waiting,
+    // con la quirk wait_for_release falta que se suelte
+    Pressed(u8),
+}
+
+impl KeyWait {
+    fn save(self, w: &mut StateWriter) {
+        let (tag, key) = match self {
+            KeyWait::Idle => (0, 0),
+            KeyWait::Waiting => (1, 0),
+            KeyWait::Pressed(key) => (2, key),
+        };
+        w.u8(tag);
+        w.u8(key);
+    }
+
+    fn load(r: &mut StateReader) -> Result<KeyWait, StateError> {
+        match (r.u8()?, r.u8()?) {
+            (0, _) => Ok(KeyWait::Idle),
+            (1, _) => Ok(KeyWait::Waiting),
+            (2, key) if (key as usize) < KEYS => Ok(KeyWait::Pressed(key)),
+            _ => Err(StateError::Invalid("key wait")),
+        }
     }
 }
 
-const MEMORY_SIZE: usize = 0x1000;
+// XO-CHIP tiene 64 KB, los demás 4 KB (ver Quirks::large_memory)
+pub(crate) const MEMORY_SIZE: usize = 0x10000;
+const SMALL_MEMORY_SIZE: usize = 0x1000;
 const REGISTERS: usize = 16;
-const PROGRAM_COUNTER_START: u16 = 0x200;
-
-const FONT: [u8; 80] = [
-    0x60, 0xa0, 0xa0, 0xa0, 0xc0,
-    0x40, 0xc0, 0x40, 0x40, 0xe0,
-    0xc0, 0x20, 0x40, 0x80, 0xe0,
-    0xc0, 0x20, 0x40, 0x20, 0xc0,
-    0x20, 0xa0, 0xe0, 0x20, 0x20,
-    0xe0, 0x80, 0xc0, 0x20, 0xc0,
-    0x40, 0x80, 0xc0, 0xa0, 0x40,
-    0xe0, 0x20, 0x60, 0x40, 0x40,
-    0x40, 0xa0, 0x40, 0xa0, 0x40,
-    0x40, 0xa0, 0x60, 0x20, 0x40,
-    0x40, 0xa0, 0xe0, 0xa0, 0xa0,
-    0xc0, 0xa0, 0xc0, 0xa0, 0xc0,
-    0x60, 0x80, 0x80, 0x80, 0x60,
-    0xc0, 0xa0, 0xa0, 0xa0, 0xc0,
-    0xe0, 0x80, 0xc0, 0x80, 0xe0,
-    0xe0, 0x80, 0xc0, 0x80, 0x80,
+
+// donde se carga la ROM y arranca el programa; el ETI-660 usa 0x600
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+// la fuente chica, con la grande a continuación
+pub const DEFAULT_FONT_ADDRESS: u16 = 0x000;
+
+// archivos que alguien podría abrir por error, por sus primeros bytes
+const NOT_ROMS: [(&[u8], &str); 5] = [
+    (b"PK\x03\x04", "zip archive"),
+    (b"GIF8", "GIF image"),
+    (b"\x89PNG", "PNG image"),
+    (b"\x7fELF", "executable"),
+    (b"CH8S", "save state"),
 ];
 
-const FONT_POSITION: usize = 0x0;
-const FILE_POSITION: usize = 0x200;
+// XO-CHIP: el patrón de audio se reproduce a 4000 * 2^((pitch - 64) / 48) bits/s
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+
+// los timers y el vblank van siempre a 60 Hz, la CPU a `ips` instrucciones
+// por segundo
+const TIMER_FREQUENCY: u32 = 60;
+pub const DEFAULT_IPS: u32 = 8 * TIMER_FREQUENCY;
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_FREQUENCY as u64);
+// si el frontend se traba no se intenta recuperar más que esto de golpe
+const DEFAULT_MAX_CATCH_UP: Duration = Duration::from_millis(250);
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
 
 pub struct Chip8 {
     memory: [u8; MEMORY_SIZE],
@@ -118,49 +419,219 @@ pub struct Chip8 {
     sound_timer: u8,
     pub screen: Screen,
     keyboard: Keyboard,
+    // flags RPL de SUPER-CHIP (Fx75/Fx85)
+    rpl_flags: [u8; REGISTERS],
+    // patrón de audio de XO-CHIP (F002), None hasta que se carga uno
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    rng: Rng,
+    quirks: Quirks,
+    vblank: bool,
+    key_wait: KeyWait,
+    // para los watchpoints del debugger, sólo se llena si está activado
+    track_memory: bool,
+    memory_accesses: Vec<MemoryAccess>,
+    // scheduler: tiempo acumulado que todavía no alcanza para una
+    // instrucción (en ns * ips) y avance hacia el próximo tick de los timers
+    // (en 1/ips de tick, se suma TIMER_FREQUENCY por instrucción)
+    ips: u32,
+    pending_time: u128,
+    tick_phase: u32,
+    max_catch_up: Duration,
+    // SHA-1 de la ROM cargada, en hexa
+    rom_hash: Option<String>,
+    font_address: u16,
+    load_address: u16,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::with_rng(quirks, Rng::new())
+    }
+
+    // misma semilla y mismas entradas, misma ejecución
+    pub fn with_seed(seed: u64) -> Chip8 {
+        Chip8::with_rng(Quirks::default(), Rng::from_state(seed))
+    }
+
+    pub fn with_rng(quirks: Quirks, rng: Rng) -> Chip8 {
         let mut c = Chip8 {
             memory: [0; MEMORY_SIZE],
             registers: [0; REGISTERS],
-            program_counter: PROGRAM_COUNTER_START,
+            program_counter: DEFAULT_LOAD_ADDRESS,
             index: 0,
             stack: Stack::new(),
             delay_timer: 0,
             sound_timer: 0,
             screen: Screen::new(),
             keyboard: Keyboard::new(),
+            rpl_flags: [0; REGISTERS],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng,
+            quirks,
+            vblank: false,
+            key_wait: KeyWait::Idle,
+            track_memory: false,
+            memory_accesses: Vec::new(),
+            ips: DEFAULT_IPS,
+            pending_time: 0,
+            tick_phase: 0,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            rom_hash: None,
+            font_address: DEFAULT_FONT_ADDRESS,
+            load_address: DEFAULT_LOAD_ADDRESS,
         };
-        c.load_font();
+        c.write_font(&Font::default());
 
         c
     }
 
-    fn load_font(&mut self) {
-        self.memory[FONT_POSITION..FONT.len()].clone_from_slice(&FONT);
+    // hay que llamarla antes de cargar la ROM, si no la fuente puede pisarla
+    pub fn set_font(&mut self, font: &Font, address: u16) -> Result<(), Chip8Error> {
+        let end = address as usize + FONT_SIZE + BIG_FONT_SIZE;
+        if end > self.memory_size() {
+            return Err(Chip8Error::MemoryOutOfBounds { addr: end - 1 });
+        }
+
+        self.font_address = address;
+        self.write_font(font);
+        Ok(())
+    }
+
+    fn write_font(&mut self, font: &Font) {
+        let small = self.font_address as usize;
+        let big = small + FONT_SIZE;
+        self.memory[small..big].copy_from_slice(&font.small);
+        self.memory[big..big + BIG_FONT_SIZE].copy_from_slice(&font.big);
+    }
+
+    pub fn font_address(&self) -> u16 {
+        self.font_address
     }
 
+    // también es donde arranca el programa, hay que llamarla antes de cargar
+    // la ROM
+    pub fn set_load_address(&mut self, address: u16) {
+        self.load_address = address;
+        self.program_counter = address;
+    }
 
-    pub fn load_rom(&mut self, file_name: &str) -> Result<(), anyhow::Error> {
-        // ver el tamaño del archivo???
-        use std::fs::File;
-        use std::io::Read;
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
 
-        let mut file = File::open(file_name)?;
+    pub fn load_rom(&mut self, file_name: &str) -> Result<(), RomError> {
+        self.load_rom_from(std::fs::File::open(file_name)?)
+    }
 
+    pub fn load_rom_from(&mut self, mut reader: impl std::io::Read) -> Result<(), RomError> {
         let mut data = vec![];
-        file.read_to_end(&mut data)?;
+        reader.read_to_end(&mut data)?;
+        self.load_rom_bytes(&data)
+    }
 
-        let length = FILE_POSITION + data.len();
-        self.memory[FILE_POSITION..length].clone_from_slice(&data);
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), RomError> {
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if let Some(&(_, kind)) = NOT_ROMS.iter().find(|(magic, _)| data.starts_with(magic)) {
+            return Err(RomError::NotARom(kind));
+        }
 
-        println!("Loaded rom");
+        let start = self.load_address as usize;
+        let max = self.memory_size().saturating_sub(start);
+        if data.len() > max {
+            return Err(RomError::TooLarge { size: data.len(), max, address: self.load_address });
+        }
+
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        self.rom_hash = Some(crate::database::rom_hash(data));
 
         Ok(())
     }
 
+    // las quirks no se guardan, son configuración y no estado de la máquina
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.bytes(&self.memory);
+        w.bytes(&self.registers);
+        w.u16(self.program_counter);
+        w.u16(self.index);
+        self.stack.save(&mut w);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        self.screen.save(&mut w);
+        self.keyboard.save(&mut w);
+        w.bytes(&self.rpl_flags);
+        w.bool(self.audio_pattern.is_some());
+        w.bytes(&self.audio_pattern.unwrap_or_default());
+        w.u8(self.pitch);
+        w.u64(self.rng.state());
+        w.bool(self.vblank);
+        self.key_wait.save(&mut w);
+
+        w.finish()
+    }
+
+    // si el estado es inválido la máquina queda como estaba
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data)?;
+        let mut c = Chip8::with_quirks(self.quirks);
+
+        c.memory.copy_from_slice(r.bytes(MEMORY_SIZE)?);
+        c.registers = r.array()?;
+        c.program_counter = r.u16()?;
+        c.index = r.u16()?;
+        c.stack = Stack::load(&mut r)?;
+        c.delay_timer = r.u8()?;
+        c.sound_timer = r.u8()?;
+        c.screen = Screen::load(&mut r)?;
+        c.keyboard = Keyboard::load(&mut r)?;
+        c.rpl_flags = r.array()?;
+        let has_pattern = r.bool()?;
+        let pattern = r.array()?;
+        c.audio_pattern = has_pattern.then_some(pattern);
+        c.pitch = r.u8()?;
+        c.rng = Rng::from_state(r.u64()?);
+        c.vblank = r.bool()?;
+        c.key_wait = KeyWait::load(&mut r)?;
+        r.finish()?;
+
+        // configuración, no estado: queda como estaba
+        c.track_memory = self.track_memory;
+        c.ips = self.ips;
+        c.max_catch_up = self.max_catch_up;
+        c.rom_hash = self.rom_hash.take();
+        c.font_address = self.font_address;
+        c.load_address = self.load_address;
+        *self = c;
+        Ok(())
+    }
+
+    // Como `load_state` pero las teclas quedan como están ahora: son las
+    // que el jugador tiene apretadas, y el frontend no se entera de las del
+    // estado. Las diferencias cuentan como apretadas o soltadas en el frame.
+    pub fn load_state_keeping_keys(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let keys = self.keyboard.keys;
+        self.load_state(data)?;
+        for (key, pressed) in keys.into_iter().enumerate() {
+            self.keyboard.set_key(key as u8, pressed);
+        }
+        Ok(())
+    }
+
     pub fn key_press(&mut self, key: u8) {
         self.keyboard.set_key(key, true);
     }
@@ -169,10 +640,75 @@ impl Chip8 {
         self.keyboard.set_key(key, false);
     }
 
+    // apretada o soltada desde el último tick de 60 Hz
+    pub fn key_just_pressed(&self, key: u8) -> bool {
+        self.keyboard.just_pressed[key as usize]
+    }
+
+    pub fn key_just_released(&self, key: u8) -> bool {
+        self.keyboard.just_released[key as usize]
+    }
+
+    // frenado en un Fx0A, para que el frontend lo pueda mostrar
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
     pub fn sound_timer(&self) -> u8 {
         self.sound_timer
     }
 
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn registers(&self) -> &[u8; REGISTERS] {
+        &self.registers
+    }
+
+    // lo que ve el programa: 4K, o 64K en XO-CHIP
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..self.memory_size()]
+    }
+
+    fn memory_size(&self) -> usize {
+        match self.quirks.large_memory {
+            true => MEMORY_SIZE,
+            false => SMALL_MEMORY_SIZE,
+        }
+    }
+
+    // cantidad de llamadas (2nnn) sin retornar
+    pub fn stack_depth(&self) -> usize {
+        self.stack.stack_pointer
+    }
+
+    pub fn opcode_at(&self, addr: u16) -> Result<u16, Chip8Error> {
+        let addr = addr as usize;
+        match self.memory().get(addr..addr + 2) {
+            Some(&[high, low]) => Ok(((high as u16) << 8) | low as u16),
+            _ => Err(Chip8Error::MemoryOutOfBounds { addr }),
+        }
+    }
+
+    pub fn set_memory_tracking(&mut self, track: bool) {
+        self.track_memory = track;
+        self.memory_accesses.clear();
+    }
+
+    // accesos del último step
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        &self.memory_accesses
+    }
+
     pub fn decrease_delay_timer(&mut self) {
         self.delay_timer = self.delay_timer.saturating_sub(1);
     }
@@ -181,90 +717,299 @@ impl Chip8 {
         self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 
-    pub fn step(&mut self) {
-        //fetch
-        let pc = self.program_counter as usize;
-        let opcode: u16 = 
-            ((self.memory[pc] as u16) << 8) | self.memory[pc + 1] as u16;
-        self.program_counter += 2;
+    pub fn audio_pattern(&self) -> Option<[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern
+    }
+
+    // bits del patrón por segundo
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn rom_hash(&self) -> Option<&str> {
+        self.rom_hash.as_deref()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // para que Cxkk dé siempre la misma secuencia
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.set_rng(Rng::from_state(seed));
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    // llamar una vez por frame, habilita el próximo Dxyn si hay display_wait
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.ips
+    }
+
+    // se puede cambiar en cualquier momento, no pierde el avance hacia el
+    // próximo tick de los timers
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        let ips = ips.max(1);
+        self.tick_phase = (self.tick_phase as u64 * ips as u64 / self.ips as u64) as u32;
+        self.ips = ips;
+    }
+
+    pub fn max_catch_up(&self) -> Duration {
+        self.max_catch_up
+    }
 
-        //decode
-        let nibbles = nibbles_from_u16(opcode);
-        let address: u16 = opcode & 0xFFF;
-        let byte_2: u8 = (opcode & 0xFF) as u8;
+    pub fn set_max_catch_up(&mut self, max: Duration) {
+        self.max_catch_up = max;
+    }
+
+    // un tick de 60 Hz: timers, vblank y fin de frame del teclado
+    fn tick(&mut self) {
+        self.decrease_delay_timer();
+        self.decrease_sound_timer();
+        self.vblank();
+        self.keyboard.end_frame();
+    }
+
+    // Avanza la emulación `elapsed` de tiempo real: corre las instrucciones
+    // que correspondan a la velocidad configurada y baja los timers a 60 Hz
+    // exactos, sin importar cada cuánto se llame. Lo que sobra se guarda para
+    // la próxima llamada. Devuelve el resultado de la última instrucción, o
+    // None si no alcanzó para ninguna.
+    pub fn run_for(&mut self, elapsed: Duration) -> Result<Option<StepOutcome>, Chip8Error> {
+        self.run_for_with(elapsed, |chip8| chip8.step().map(Some))
+    }
+
+    // Como `run_for` pero cada instrucción la ejecuta `step`, para que el
+    // debugger pueda meterse en el medio. Si `step` devuelve None (no
+    // ejecutó nada) se descarta el tiempo que quedaba.
+    pub fn run_for_with<F>(&mut self, elapsed: Duration, step: F)
+        -> Result<Option<StepOutcome>, Chip8Error>
+    where
+        F: FnMut(&mut Chip8) -> Result<Option<StepOutcome>, Chip8Error>,
+    {
+        let ips = self.ips as u128;
+        let limit = self.max_catch_up.as_nanos() * ips;
+
+        self.pending_time = (self.pending_time + elapsed.as_nanos() * ips).min(limit);
+        let cycles = self.pending_time / NANOS_PER_SECOND;
+        self.pending_time %= NANOS_PER_SECOND;
+
+        let outcome = self.run_cycles_with(cycles as u64, step)?;
+        if cycles > 0 && matches!(outcome, None | Some(StepOutcome::Exited)) {
+            self.pending_time = 0;
+        }
+
+        Ok(outcome)
+    }
+
+    // corre `cycles` instrucciones, bajando los timers cada ips / 60
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<Option<StepOutcome>, Chip8Error> {
+        self.run_cycles_with(cycles, |chip8| chip8.step().map(Some))
+    }
+
+    fn run_cycles_with<F>(&mut self, cycles: u64, mut step: F)
+        -> Result<Option<StepOutcome>, Chip8Error>
+    where
+        F: FnMut(&mut Chip8) -> Result<Option<StepOutcome>, Chip8Error>,
+    {
+        let mut last = None;
+
+        for _ in 0..cycles {
+            let Some(outcome) = step(self)? else {
+                return Ok(None);
+            };
+            last = Some(outcome);
+
+            if outcome == StepOutcome::Exited {
+                break;
+            }
+
+            self.tick_phase += TIMER_FREQUENCY;
+            while self.tick_phase >= self.ips {
+                self.tick_phase -= self.ips;
+                self.tick();
+            }
+        }
+
+        Ok(last)
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        //fetch
+        let pc = self.program_counter;
+        let opcode = self.opcode_at(pc)?;
+        self.program_counter = pc.wrapping_add(2);
+        self.memory_accesses.clear();
 
         //execute
-        self.execute(nibbles, address, byte_2);
+        self.execute(pc, opcode)
+    }
+
+    fn read_memory(&mut self, addr: usize) -> Result<u8, Chip8Error> {
+        let val = self.memory()
+            .get(addr)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })?;
+        if self.track_memory {
+            self.memory_accesses.push(MemoryAccess::Read(addr));
+        }
+        Ok(val)
+    }
+
+    fn write_memory(&mut self, addr: usize, val: u8) -> Result<(), Chip8Error> {
+        let size = self.memory_size();
+        let byte = self.memory[..size]
+            .get_mut(addr)
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })?;
+        *byte = val;
+        if self.track_memory {
+            self.memory_accesses.push(MemoryAccess::Write(addr));
+        }
+        Ok(())
+    }
+
+    fn key_pressed(&self, pc: u16, key: u8) -> Result<bool, Chip8Error> {
+        if key as usize >= KEYS {
+            return Err(Chip8Error::InvalidKey { pc, key });
+        }
+        Ok(self.keyboard.is_pressed(key))
     }
 
     #[allow(non_snake_case)]
-    fn execute(&mut self, nibbles: [u8; 4], address: u16, byte_2: u8) {
-        match nibbles {
-            [0x0, 0x0, 0x0, 0x0] => {
+    fn execute(&mut self, pc: u16, opcode: u16) -> Result<StepOutcome, Chip8Error> {
+        //decode, con el mismo decodificador que el disassembler
+        match disassemble(opcode) {
+            Instruction::Nop => {
                 // nothing
             }
-            [0x0, 0x0, 0xE, 0x0] => {
+            Instruction::ScrollDown(N) => {
+                // scroll down N
+                self.screen.scroll_down(N as usize);
+            },
+            Instruction::ScrollUp(N) => {
+                // scroll up N
+                self.screen.scroll_up(N as usize);
+            },
+            Instruction::Clear => {
                 // clear
                 self.clear();
             },
-            [0x0, 0x0, 0xE, 0xE] => {
+            Instruction::Return => {
                 // return
-                self.program_counter = self.stack.pop();
+                self.program_counter = self.stack
+                    .pop()
+                    .ok_or(Chip8Error::StackUnderflow { pc })?;
+            },
+            Instruction::ScrollRight => {
+                // scroll right 4
+                self.screen.scroll_right(4);
+            },
+            Instruction::ScrollLeft => {
+                // scroll left 4
+                self.screen.scroll_left(4);
+            },
+            Instruction::Exit => {
+                // exit
+                self.program_counter = pc;
+                return Ok(StepOutcome::Exited);
+            },
+            Instruction::LowRes => {
+                // lores
+                self.screen.set_hires(false);
             },
-            [0x1, _, _, _] => {
+            Instruction::HighRes => {
+                // hires
+                self.screen.set_hires(true);
+            },
+            Instruction::Jump(address) => {
                 // jump
                 self.program_counter = address;
             },
-            [0x2, _, _, _] => {
+            Instruction::Call(address) => {
                 // call
-                self.stack.push(self.program_counter);
+                self.stack
+                    .push(self.program_counter)
+                    .ok_or(Chip8Error::StackOverflow { pc })?;
                 self.program_counter = address;
             },
-            [0x3, X, _, _] => {
+            Instruction::SkipEqualByte(X, byte_2) => {
                 // skip if equal
                 if self.registers[X as usize] == byte_2 {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             },
-            [0x4, X, _, _] => {
+            Instruction::SkipNotEqualByte(X, byte_2) => {
                 // skip if not equal
                 if self.registers[X as usize] != byte_2 {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             },
-            [0x5, X, Y, 0x0] => {
+            Instruction::SkipEqual(X, Y) => {
                 // skip if X == Y
                 if self.registers[X as usize] == self.registers[Y as usize] {
-                    self.program_counter += 2;
+                    self.skip();
+                }
+            },
+            Instruction::SaveRange(X, Y) => {
+                // save VX..VY to memory
+                let index = self.index as usize;
+                for (i, r) in Chip8::register_range(X, Y).into_iter().enumerate() {
+                    self.write_memory(index + i, self.registers[r])?;
+                }
+            },
+            Instruction::LoadRange(X, Y) => {
+                // load VX..VY from memory
+                let index = self.index as usize;
+                for (i, r) in Chip8::register_range(X, Y).into_iter().enumerate() {
+                    self.registers[r] = self.read_memory(index + i)?;
                 }
             },
-            [0x6, X, _, _] => {
+            Instruction::SetByte(X, byte_2) => {
                 // set
                 self.registers[X as usize] = byte_2;
             },
-            [0x7, X, _, _] => {
+            Instruction::AddByte(X, byte_2) => {
                 // add
                 let X = X as usize;
 
                 self.registers[X] = self.registers[X].wrapping_add(byte_2);
             },
-            [0x8, X, Y, 0x0] => {
+            Instruction::Set(X, Y) => {
                 // set
                 self.registers[X as usize] = self.registers[Y as usize];
             },
-            [0x8, X, Y, 0x1] => {
+            Instruction::Or(X, Y) => {
                 // or
                 self.registers[X as usize] |= self.registers[Y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
             },
-            [0x8, X, Y, 0x2] => {
+            Instruction::And(X, Y) => {
                 // and
                 self.registers[X as usize] &= self.registers[Y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
             },
-            [0x8, X, Y, 0x3] => {
+            Instruction::Xor(X, Y) => {
                 // xor
                 self.registers[X as usize] ^= self.registers[Y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
             },
-            [0x8, X, Y, 0x4] => {
+            Instruction::Add(X, Y) => {
                 // cuando esto salga de nightly, usarlo acá: https://doc.rust-lang.org/std/primitive.u8.html#method.carrying_add
                 // add
                 let (X, Y) = (X as usize, Y as usize);
@@ -275,7 +1020,7 @@ impl Chip8 {
                 self.registers[X] = val_x.wrapping_add(val_y);
                 self.registers[0xF] = val_x.checked_add(val_y).is_none().into();
             },
-            [0x8, X, Y, 0x5] => {
+            Instruction::Sub(X, Y) => {
                 // mismo problema del carrying add
                 // sub
                 let (X, Y) = (X as usize, Y as usize);
@@ -286,14 +1031,19 @@ impl Chip8 {
                 self.registers[X] = val_x.wrapping_sub(val_y);
                 self.registers[0xF] = val_x.checked_sub(val_y).is_some().into();
             },
-            [0x8, X, _, 0x6] => {
+            Instruction::ShiftRight(X, Y) => {
                 // shift right
-                let X = X as usize;
+                let (X, Y) = (X as usize, Y as usize);
+
+                let val = match self.quirks.shift_uses_vy {
+                    true => self.registers[Y],
+                    false => self.registers[X],
+                };
 
-                self.registers[0xF] = self.registers[X] & 0x1;
-                self.registers[X] >>= 1;
+                self.registers[X] = val >> 1;
+                self.registers[0xF] = val & 0x1;
             },
-            [0x8, X, Y, 0x7] => {
+            Instruction::SubReverse(X, Y) => {
                 // Y - X
                 let (X, Y) = (X as usize, Y as usize);
 
@@ -301,157 +1051,908 @@ impl Chip8 {
                 let val_y = self.registers[Y];
 
                 self.registers[X] = val_y.wrapping_sub(val_x);
-                self.registers[0xF] = (val_y > val_x).into();
+                self.registers[0xF] = val_y.checked_sub(val_x).is_some().into();
             },
-            [0x8, X, _, 0xE] => {
+            Instruction::ShiftLeft(X, Y) => {
                 // shift left
-                let X = X as usize;
+                let (X, Y) = (X as usize, Y as usize);
 
-                self.registers[0xF] = self.registers[X] >> 7;
-                self.registers[X] <<= 1;
+                let val = match self.quirks.shift_uses_vy {
+                    true => self.registers[Y],
+                    false => self.registers[X],
+                };
+
+                self.registers[X] = val << 1;
+                self.registers[0xF] = val >> 7;
             },
-            [0x9, X, Y, 0x0] => {
+            Instruction::SkipNotEqual(X, Y) => {
                 // skip if X != Y
                 let (X, Y) = (X as usize, Y as usize);
 
                 if self.registers[X] != self.registers[Y] {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             },
-            [0xA, _, _, _] => {
+            Instruction::SetIndex(address) => {
                 // set index
                 self.index = address;
             },
-            [0xB, _, _, _] => {
-                // jump V0 + address
-                self.program_counter = address + self.registers[0] as u16;
+            Instruction::JumpOffset(address) => {
+                // jump V0 + address (o VX + address, con X el primer nibble)
+                let offset = match self.quirks.jump_uses_vx {
+                    true => self.registers[(address >> 8) as usize],
+                    false => self.registers[0],
+                };
+                self.program_counter = address + offset as u16;
             },
-            [0xC, X, _, _] => {
+            Instruction::Random(X, byte_2) => {
                 // random
                 let X = X as usize;
-                self.registers[X] = rand::random::<u8>() & byte_2;
+                self.registers[X] = self.rng.next_u8() & byte_2;
             }
-            [0xD, X, Y, N] => {
+            Instruction::Draw(X, Y, N) => {
                 // draw
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        self.program_counter = pc;
+                        return Ok(StepOutcome::WaitingForVblank);
+                    }
+                    self.vblank = false;
+                }
+
                 let (X, Y, N) = (X as usize, Y as usize, N as usize);
-                self.draw(self.registers[X] as usize, self.registers[Y] as usize, N);
+                self.draw(self.registers[X] as usize, self.registers[Y] as usize, N)?;
 
             },
-            [0xE, X, 0x9, 0xE] => {
+            Instruction::SkipKey(X) => {
                 // skip if key is pressed
                 let X = X as usize;
-                if self.keyboard.is_pressed(self.registers[X]) {
-                    self.program_counter += 2;
+                if self.key_pressed(pc, self.registers[X])? {
+                    self.skip();
                 }
             },
-            [0xE, X, 0xA, 0x1] => {
+            Instruction::SkipNotKey(X) => {
                 // skip if key is not pressed
                 let X = X as usize;
-                if !self.keyboard.is_pressed(self.registers[X]) {
-                    self.program_counter += 2;
+                if !self.key_pressed(pc, self.registers[X])? {
+                    self.skip();
                 }
             },
-            [0xF, X, 0x0, 0x7] => {
+            Instruction::LongPrefix => {
+                // long index load, los 16 bits siguientes son la dirección
+                let next = self.program_counter as usize;
+                self.index = ((self.read_memory(next)? as u16) << 8)
+                    | self.read_memory(next + 1)? as u16;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            },
+            Instruction::Planes(N) => {
+                // select planes
+                self.screen.select_planes(N);
+            },
+            Instruction::Audio => {
+                // load audio pattern
+                let index = self.index as usize;
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(index + i)?;
+                }
+                self.audio_pattern = Some(pattern);
+            },
+            Instruction::GetDelay(X) => {
                 // get delay timer
                 let X = X as usize;
                 self.registers[X] = self.delay_timer;
             },
-            [0xF, X, 0, 0xA] => {
-                // wait for key press
+            Instruction::WaitKey(X) => {
+                // wait for key press (and release)
                 let X = X as usize;
 
-                match self.keyboard.keys_pressed().first() {
-                    Some(key) => {
-                        self.registers[X] = *key;
+                // una tecla que ya estaba apretada no cuenta hasta que se
+                // vuelva a apretar, salvo que se espere a soltarla
+                if let KeyWait::Idle | KeyWait::Waiting = self.key_wait {
+                    let key = match self.quirks.wait_for_release {
+                        true => Keyboard::first(&self.keyboard.just_pressed)
+                            .or(Keyboard::first(&self.keyboard.keys)),
+                        false => Keyboard::first(&self.keyboard.just_pressed),
+                    };
+                    self.key_wait = key.map_or(KeyWait::Waiting, KeyWait::Pressed);
+                }
+
+                match self.key_wait {
+                    KeyWait::Pressed(key)
+                        if !self.quirks.wait_for_release || !self.keyboard.is_pressed(key) =>
+                    {
+                        self.registers[X] = key;
+                        self.key_wait = KeyWait::Idle;
+                    },
+                    _ => {
+                        self.program_counter = pc;
+                        return Ok(StepOutcome::WaitingForKey);
                     },
-                    None => {
-                        self.program_counter -= 2;
-                    }
                 }
             },
-            [0xF, X, 0x1, 0x5] => {
+            Instruction::SetDelay(X) => {
                 // set delay timer
                 let X = X as usize;
                 self.delay_timer = self.registers[X];
             },
-            [0xF, X, 0x1, 0x8] => {
+            Instruction::SetSound(X) => {
                 // set sound timer
                 let X = X as usize;
                 self.sound_timer = self.registers[X];
             },
-            [0xF, X, 0x1, 0xE] => {
+            Instruction::AddIndex(X) => {
                 // increment index
                 let X = X as usize;
                 self.index = self.index.wrapping_add(self.registers[X] as u16);
             },
-            [0xF, X, 0x2, 0x9] => {
+            Instruction::Font(X) => {
                 // set index to font sprite for vx
-                let X = X as usize;
-                self.index = FONT_POSITION as u16 + self.registers[X] as u16;
+                let digit = (self.registers[X as usize] & 0xf) as u16;
+                self.index = self.font_address + digit * GLYPH_SIZE as u16;
+            },
+            Instruction::BigFont(X) => {
+                // set index to big font sprite for vx; como en Fx29 se usa el
+                // nibble de abajo, y de A a F queda pasando los 10 dígitos,
+                // igual que en SUPER-CHIP
+                let digit = (self.registers[X as usize] & 0xf) as usize;
+                let offset = FONT_SIZE + digit * BIG_GLYPH_SIZE;
+                self.index = self.font_address + offset as u16;
             },
-            [0xF, X, 0x3, 0x3] => {
+            Instruction::Bcd(X) => {
                 // decode vx to bcd
                 let vx = self.registers[X as usize];
                 let index = self.index as usize;
-                self.memory[index] = vx / 100;
-                self.memory[index + 1] = vx / 10 % 10;
-                self.memory[index + 2] = vx % 10; 
+                self.write_memory(index, vx / 100)?;
+                self.write_memory(index + 1, vx / 10 % 10)?;
+                self.write_memory(index + 2, vx % 10)?;
             },
-            [0xF, X, 0x5, 0x5] => {
+            Instruction::Pitch(X) => {
+                // set pitch
+                self.pitch = self.registers[X as usize];
+            },
+            Instruction::Store(X) => {
                 // save registers to memory
                 for i in 0..=(X as usize) {
-                    self.memory[self.index as usize + i] = self.registers[i];
+                    self.write_memory(self.index as usize + i, self.registers[i])?;
+                }
+                if self.quirks.load_store_increments_index {
+                    self.index = self.index.wrapping_add(X as u16 + 1);
                 }
             },
-            [0xF, X, 0x6, 0x5] => {
+            Instruction::Restore(X) => {
                 // load registers from memory
                 for i in 0..=(X as usize) {
-                    self.registers[i] = self.memory[self.index as usize + i];
+                    self.registers[i] = self.read_memory(self.index as usize + i)?;
                 }
-            }
-            _ => {
-                eprintln!("Unknown opcode {:x}, {:x}, {:x}, {:x}", nibbles[0], nibbles[1], nibbles[2], nibbles[3]);
+                if self.quirks.load_store_increments_index {
+                    self.index = self.index.wrapping_add(X as u16 + 1);
+                }
+            },
+            Instruction::SaveFlags(X) => {
+                // save registers to rpl flags
+                let X = X as usize;
+                self.rpl_flags[..=X].copy_from_slice(&self.registers[..=X]);
+            },
+            Instruction::LoadFlags(X) => {
+                // load registers from rpl flags
+                let X = X as usize;
+                self.registers[..=X].copy_from_slice(&self.rpl_flags[..=X]);
+            },
+            // SetIndexLong sólo sale de disassemble_at, acá es LongPrefix
+            Instruction::Data(_) | Instruction::SetIndexLong(_) => {
+                return Err(Chip8Error::UnknownOpcode { pc, opcode });
             }
 
         }
+
+        Ok(StepOutcome::Executed)
     }
 
     fn clear(&mut self) {
-        for x in 0..SCREEN_WIDTH {
-            for y in 0..SCREEN_HEIGHT {
-                self.screen.set_pixel((x, y), false);
+        self.screen.clear();
+    }
+
+    fn draw(&mut self, x: usize, y: usize, n: usize) -> Result<(), Chip8Error> {
+        self.registers[0xF] = 0;
+
+        // Dxy0 dibuja un sprite de 16x16
+        let (sprite_width, sprite_height) = match n {
+            0 => (16, 16),
+            n => (8, n),
+        };
+        let sprite_size = sprite_width / 8 * sprite_height;
+
+        // con varios planos seleccionados, los datos de cada plano van uno
+        // atrás del otro
+        let mut addr = self.index as usize;
+        for plane in 0..PLANES {
+            let plane = 1 << plane;
+            if self.screen.planes() & plane == 0 {
+                continue;
             }
+
+            if self.draw_plane(x, y, (sprite_width, sprite_height), addr, plane)? {
+                self.registers[0xF] = 1;
+            }
+            addr += sprite_size;
         }
+
+        Ok(())
     }
 
-    fn draw(&mut self, x: usize, y: usize, n: usize) {
-        self.registers[0xF] = 0;
+    fn draw_plane(
+        &mut self,
+        x: usize,
+        y: usize,
+        (sprite_width, sprite_height): (usize, usize),
+        addr: usize,
+        plane: u8,
+    ) -> Result<bool, Chip8Error> {
+        let (width, height) = (self.screen.width(), self.screen.height());
+        let mut collision = false;
+
+        // la posición inicial siempre da la vuelta
+        let (x, y) = (x % width, y % height);
 
-        for i in 0..n {
-            let y = (y + i) % SCREEN_HEIGHT;
+        for i in 0..sprite_height {
+            if self.quirks.clip_sprites && y + i >= height {
+                break;
+            }
+            let y = (y + i) % height;
 
-            let val = self.memory[self.index as usize + i]; // fila de 8 pixeles
+            // fila de 8 o 16 pixeles, alineada a la izquierda
+            let row: u16 = match sprite_width {
+                16 => {
+                    let addr = addr + 2 * i;
+                    ((self.read_memory(addr)? as u16) << 8) | self.read_memory(addr + 1)? as u16
+                },
+                _ => (self.read_memory(addr + i)? as u16) << 8,
+            };
 
-            for j in 0..8 {
-                let x = (x + j) % SCREEN_WIDTH;
-                let pixel = ((val >> (7 - j)) & 0x1) == 1;
-                let current = self.screen.get_pixel((x, y));
+            for j in 0..sprite_width {
+                if self.quirks.clip_sprites && x + j >= width {
+                    break;
+                }
+                let x = (x + j) % width;
+                let pixel = ((row >> (15 - j)) & 0x1) == 1;
+                let current = self.screen.is_set((x, y), plane);
                 let new = pixel ^ current;
 
-                self.screen.set_pixel((x, y), new);
+                self.screen.set_pixel((x, y), plane, new);
 
                 if current & !new {
-                    self.registers[0xF] = 1;
+                    collision = true;
                 }
             }
         }
+
+        Ok(collision)
+    }
+
+    // si la próxima instrucción es F000 nnnn hay que saltear 4 bytes
+    fn skip(&mut self) {
+        let pc = self.program_counter as usize;
+        let long = self.memory().get(pc..pc + 2) == Some(&[0xF0, 0x00]);
+
+        let size = if long { 4 } else { 2 };
+        self.program_counter = self.program_counter.wrapping_add(size);
+    }
+
+    // registros VX..VY, en cualquier orden
+    fn register_range(x: u8, y: u8) -> Vec<usize> {
+        match x <= y {
+            true => (x..=y).map(usize::from).collect(),
+            false => (y..=x).rev().map(usize::from).collect(),
+        }
     }
 
 }
 
-fn nibbles_from_u16(var: u16) -> [u8; 4] {
-    [(var >> 12) as u8,
-    (var >> 8 & 0xF) as u8,
-    (var >> 4 & 0xF) as u8,
-    (var & 0xF) as u8]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // carga el programa en 0x200, con la misma semilla siempre
+    fn chip8_with(quirks: Quirks, program: &[u16]) -> Chip8 {
+        let mut c = Chip8::with_rng(quirks, Rng::from_state(1));
+        for (i, opcode) in program.iter().enumerate() {
+            let addr = DEFAULT_LOAD_ADDRESS as usize + 2 * i;
+            c.memory[addr..addr + 2].copy_from_slice(&opcode.to_be_bytes());
+        }
+        c
+    }
+
+    fn chip8(program: &[u16]) -> Chip8 {
+        chip8_with(Quirks::MODERN, program)
+    }
+
+    // corre una instrucción por opcode del programa
+    fn run(c: &mut Chip8, steps: usize) {
+        for _ in 0..steps {
+            assert_eq!(c.step(), Ok(StepOutcome::Executed));
+        }
+    }
+
+    // VX op VY con VX = x y VY = y, devuelve (VX, VF)
+    fn alu(op: u16, x: u8, y: u8) -> (u8, u8) {
+        let mut c = chip8(&[0x8010 | op]);
+        c.registers[0] = x;
+        c.registers[1] = y;
+        run(&mut c, 1);
+        (c.registers[0], c.registers[0xF])
+    }
+
+    #[test]
+    fn clear_screen() {
+        let mut c = chip8(&[0x00E0]);
+        c.screen.set_pixel((3, 4), 1, true);
+        run(&mut c, 1);
+        assert!(!c.screen.get_pixel((3, 4)));
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut c = chip8(&[0x2206, 0x0000, 0x0000, 0x00EE]);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x206);
+        assert_eq!(c.stack_depth(), 1);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x202);
+        assert_eq!(c.stack_depth(), 0);
+    }
+
+    #[test]
+    fn return_with_empty_stack() {
+        let mut c = chip8(&[0x00EE]);
+        assert_eq!(c.step(), Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn call_overflows_stack() {
+        // se llama a sí mismo para siempre
+        let mut c = chip8(&[0x2200]);
+        run(&mut c, STACK_SIZE);
+        assert_eq!(c.step(), Err(Chip8Error::StackOverflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn jump() {
+        let mut c = chip8(&[0x1ABC]);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0xABC);
+    }
+
+    #[test]
+    fn jump_with_offset() {
+        let mut c = chip8(&[0xB300]);
+        c.registers[0] = 0x12;
+        c.registers[3] = 0x34;
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x312);
+
+        let mut c = chip8_with(Quirks::SUPER_CHIP, &[0xB300]);
+        c.registers[0] = 0x12;
+        c.registers[3] = 0x34;
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x334);
+    }
+
+    #[test]
+    fn skips() {
+        // (opcode, V0, V1, salta)
+        let cases = [
+            (0x3042, 0x42, 0, true),
+            (0x3042, 0x41, 0, false),
+            (0x4042, 0x42, 0, false),
+            (0x4042, 0x41, 0, true),
+            (0x5010, 7, 7, true),
+            (0x5010, 7, 8, false),
+            (0x9010, 7, 7, false),
+            (0x9010, 7, 8, true),
+        ];
+
+        for (opcode, v0, v1, skips) in cases {
+            let mut c = chip8(&[opcode]);
+            c.registers[0] = v0;
+            c.registers[1] = v1;
+            run(&mut c, 1);
+            let expected = if skips { 0x204 } else { 0x202 };
+            assert_eq!(c.program_counter, expected, "{:04X} V0={} V1={}", opcode, v0, v1);
+        }
+    }
+
+    #[test]
+    fn skip_over_long_load() {
+        let mut c = chip8(&[0x3000, 0xF000, 0x1234]);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x206);
+    }
+
+    #[test]
+    fn set_and_add_byte() {
+        let mut c = chip8(&[0x6AFE, 0x7A03]);
+        c.registers[0xF] = 0x55;
+        run(&mut c, 2);
+        // 7xkk da la vuelta sin tocar VF
+        assert_eq!(c.registers[0xA], 0x01);
+        assert_eq!(c.registers[0xF], 0x55);
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(alu(0x0, 0x12, 0x34).0, 0x34);
+        assert_eq!(alu(0x1, 0b1100, 0b1010).0, 0b1110);
+        assert_eq!(alu(0x2, 0b1100, 0b1010).0, 0b1000);
+        assert_eq!(alu(0x3, 0b1100, 0b1010).0, 0b0110);
+    }
+
+    #[test]
+    fn logic_resets_vf() {
+        for op in [0x8011, 0x8012, 0x8013] {
+            let mut c = chip8_with(Quirks::COSMAC_VIP, &[op]);
+            c.registers[0xF] = 1;
+            run(&mut c, 1);
+            assert_eq!(c.registers[0xF], 0, "{:04X}", op);
+
+            let mut c = chip8(&[op]);
+            c.registers[0xF] = 1;
+            run(&mut c, 1);
+            assert_eq!(c.registers[0xF], 1, "{:04X}", op);
+        }
+    }
+
+    #[test]
+    fn add_sets_carry() {
+        assert_eq!(alu(0x4, 0x10, 0x20), (0x30, 0));
+        assert_eq!(alu(0x4, 0xFF, 0x01), (0x00, 1));
+        assert_eq!(alu(0x4, 0xFF, 0xFF), (0xFE, 1));
+    }
+
+    #[test]
+    fn sub_sets_no_borrow() {
+        assert_eq!(alu(0x5, 0x30, 0x10), (0x20, 1));
+        assert_eq!(alu(0x5, 0x10, 0x30), (0xE0, 0));
+        assert_eq!(alu(0x5, 0x42, 0x42), (0x00, 1));
+    }
+
+    #[test]
+    fn reverse_sub_sets_no_borrow() {
+        assert_eq!(alu(0x7, 0x10, 0x30), (0x20, 1));
+        assert_eq!(alu(0x7, 0x30, 0x10), (0xE0, 0));
+        assert_eq!(alu(0x7, 0x42, 0x42), (0x00, 1));
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(alu(0x6, 0x05, 0xFF), (0x02, 1));
+        assert_eq!(alu(0x6, 0x04, 0xFF), (0x02, 0));
+        assert_eq!(alu(0xE, 0x81, 0x00), (0x02, 1));
+        assert_eq!(alu(0xE, 0x41, 0xFF), (0x82, 0));
+    }
+
+    #[test]
+    fn shift_uses_vy() {
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0x8016, 0x823E]);
+        c.registers[1] = 0x03;
+        c.registers[3] = 0x80;
+        run(&mut c, 2);
+        assert_eq!(c.registers[0], 0x01);
+        assert_eq!(c.registers[2], 0x00);
+        assert_eq!(c.registers[0xF], 1);
+    }
+
+    #[test]
+    fn flag_wins_over_result_in_vf() {
+        // con VF como VX el resultado se pisa con el flag
+        for (op, x, y, flag) in [(0x4, 0xFF, 0x02, 1), (0x5, 0x01, 0x02, 0), (0x7, 0x01, 0x02, 1)] {
+            let mut c = chip8(&[0x8F00 | op]);
+            c.registers[0xF] = x;
+            c.registers[0] = y;
+            run(&mut c, 1);
+            assert_eq!(c.registers[0xF], flag, "8F0{:X}", op);
+        }
+
+        let mut c = chip8(&[0x8F06]);
+        c.registers[0xF] = 0x02;
+        run(&mut c, 1);
+        assert_eq!(c.registers[0xF], 0);
+    }
+
+    #[test]
+    fn index() {
+        let mut c = chip8(&[0xA123, 0xF51E]);
+        c.registers[5] = 0x10;
+        run(&mut c, 2);
+        assert_eq!(c.index, 0x133);
+    }
+
+    #[test]
+    fn long_index_load() {
+        let mut c = chip8(&[0xF000, 0xBEEF]);
+        run(&mut c, 1);
+        assert_eq!(c.index, 0xBEEF);
+        assert_eq!(c.program_counter, 0x204);
+    }
+
+    #[test]
+    fn font_sprites() {
+        let mut c = chip8(&[0xF029, 0xF129, 0xF230]);
+        c.registers[..3].copy_from_slice(&[0x0, 0xA, 7]);
+        run(&mut c, 1);
+        assert_eq!(c.index, DEFAULT_FONT_ADDRESS);
+        run(&mut c, 1);
+        assert_eq!(c.index, DEFAULT_FONT_ADDRESS + 0xA * 5);
+        assert_eq!(c.memory[c.index as usize..][..5], [0xf0, 0x90, 0xf0, 0x90, 0x90]);
+        run(&mut c, 1);
+        assert_eq!(c.index, DEFAULT_FONT_ADDRESS + 80 + 7 * 10);
+    }
+
+    #[test]
+    fn load_rom_validation() {
+        let mut c = Chip8::new();
+        assert!(matches!(c.load_rom_bytes(&[]), Err(RomError::Empty)));
+        assert!(matches!(c.load_rom_bytes(b"PK\x03\x04rest"), Err(RomError::NotARom(_))));
+        assert!(c.load_rom_bytes(&[0; 0xE00]).is_ok());
+        assert!(matches!(
+            c.load_rom_bytes(&[0; 0xE01]),
+            Err(RomError::TooLarge { size: 0xE01, max: 0xE00, address: 0x200 })
+        ));
+
+        let mut c = Chip8::with_quirks(Quirks::XO_CHIP);
+        assert!(c.load_rom_bytes(&[0; 0xE01]).is_ok());
+    }
+
+    #[test]
+    fn load_rom_at_another_address() {
+        let mut c = Chip8::new();
+        c.set_load_address(0x600);
+        c.load_rom_from(&[0x12, 0x34][..]).unwrap();
+        assert_eq!(c.program_counter, 0x600);
+        assert_eq!(c.opcode_at(0x600), Ok(0x1234));
+        assert!(c.load_rom_bytes(&[0; 0xA01]).is_err());
+    }
+
+    #[test]
+    fn font_at_another_address() {
+        let mut c = chip8(&[0xF029]);
+        let font = crate::font::FontSet::Eti660.font();
+        c.set_font(&font, 0x50).unwrap();
+        c.registers[0] = 3;
+        run(&mut c, 1);
+        assert_eq!(c.index, 0x50 + 3 * 5);
+        assert_eq!(c.memory[0x50..0x50 + 80], font.small);
+        assert!(c.set_font(&font, 0xFFF0).is_err());
+    }
+
+    #[test]
+    fn glyph_addresses_for_every_font_set() {
+        for set in crate::font::FontSet::ALL {
+            let font = set.font();
+            for digit in 0..16 {
+                let mut c = chip8(&[0xF029]);
+                c.set_font(&font, 0x100).unwrap();
+                c.registers[0] = digit as u8;
+                run(&mut c, 1);
+                assert_eq!(c.index as usize, 0x100 + digit * 5, "{} {:X}", set, digit);
+                assert_eq!(c.memory[c.index as usize..][..5], font.small[digit * 5..][..5]);
+            }
+            for digit in 0..10 {
+                let mut c = chip8(&[0xF030]);
+                c.set_font(&font, 0x100).unwrap();
+                c.registers[0] = digit as u8;
+                run(&mut c, 1);
+                assert_eq!(c.index as usize, 0x100 + 80 + digit * 10, "{} {}", set, digit);
+                assert_eq!(c.memory[c.index as usize..][..10], font.big[digit * 10..][..10]);
+            }
+        }
+    }
+
+    #[test]
+    fn big_font_uses_the_low_nibble() {
+        let mut c = chip8(&[0xF030, 0xF130]);
+        c.registers[..2].copy_from_slice(&[0x13, 0xFA]);
+        run(&mut c, 1);
+        assert_eq!(c.index, DEFAULT_FONT_ADDRESS + 80 + 3 * 10);
+        run(&mut c, 1);
+        assert_eq!(c.index, DEFAULT_FONT_ADDRESS + 80 + 0xA * 10);
+    }
+
+    // I cerca del final de los 4K, cada instrucción se pasa en 0x1000
+    #[test]
+    fn store_registers_out_of_bounds() {
+        let mut c = chip8(&[0xAFFE, 0xF555]);
+        run(&mut c, 1);
+        assert_eq!(c.step(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 }));
+    }
+
+    #[test]
+    fn load_registers_out_of_bounds() {
+        let mut c = chip8(&[0xAFFE, 0xF565]);
+        run(&mut c, 1);
+        assert_eq!(c.step(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 }));
+    }
+
+    #[test]
+    fn bcd_out_of_bounds() {
+        let mut c = chip8(&[0xAFFE, 0xF033]);
+        run(&mut c, 1);
+        assert_eq!(c.step(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 }));
+    }
+
+    #[test]
+    fn sprite_out_of_bounds() {
+        let mut c = chip8(&[0xAFFC, 0xD018]);
+        run(&mut c, 1);
+        assert_eq!(c.step(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 }));
+    }
+
+    #[test]
+    fn fetch_out_of_bounds() {
+        let mut c = chip8(&[]);
+        c.program_counter = 0xFFF;
+        assert_eq!(c.step(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xFFF }));
+    }
+
+    #[test]
+    fn memory_size_depends_on_the_platform() {
+        let out = Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 });
+
+        // LD I, 0xFFE; LD V5, [I]
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0xAFFE, 0xF565]);
+        run(&mut c, 1);
+        assert_eq!(c.step(), out);
+        let mut c = chip8_with(Quirks::XO_CHIP, &[0xAFFE, 0xF565]);
+        run(&mut c, 2);
+
+        // JP 0xFFE, ahí hay un 0000 y después se sale de la memoria
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0x1FFE]);
+        c.memory[0xFFE..0x1000].copy_from_slice(&[0x00, 0xE0]);
+        run(&mut c, 2);
+        assert_eq!(c.step(), out);
+        let mut c = chip8_with(Quirks::XO_CHIP, &[0x1FFE]);
+        c.memory[0xFFE..0x1000].copy_from_slice(&[0x00, 0xE0]);
+        run(&mut c, 3);
+
+        let font = Font::default();
+        assert!(Chip8::with_quirks(Quirks::COSMAC_VIP).set_font(&font, 0xFA0).is_err());
+        assert!(Chip8::with_quirks(Quirks::XO_CHIP).set_font(&font, 0xFA0).is_ok());
+        assert_eq!(Chip8::with_quirks(Quirks::COSMAC_VIP).memory().len(), 0x1000);
+    }
+
+    #[test]
+    fn random_is_masked_and_seeded() {
+        let mut a = chip8(&[0xC00F, 0xC1FF]);
+        let mut b = chip8(&[0xC00F, 0xC1FF]);
+        run(&mut a, 2);
+        run(&mut b, 2);
+        assert_eq!(a.registers[0] & 0xF0, 0);
+        assert_eq!(a.registers, b.registers);
+    }
+
+    #[test]
+    fn draw_sets_pixels_and_collision() {
+        // un sprite de una fila 0b1010_0000 en (2, 3), dos veces
+        let mut c = chip8(&[0xA300, 0xD011, 0xD011]);
+        c.memory[0x300] = 0b1010_0000;
+        c.registers[0] = 2;
+        c.registers[1] = 3;
+
+        run(&mut c, 2);
+        assert!(c.screen.get_pixel((2, 3)));
+        assert!(!c.screen.get_pixel((3, 3)));
+        assert!(c.screen.get_pixel((4, 3)));
+        assert_eq!(c.registers[0xF], 0);
+
+        run(&mut c, 1);
+        assert!(!c.screen.get_pixel((2, 3)));
+        assert!(!c.screen.get_pixel((4, 3)));
+        assert_eq!(c.registers[0xF], 1);
+    }
+
+    #[test]
+    fn draw_wraps_start_position() {
+        let mut c = chip8(&[0xA300, 0xD011]);
+        c.memory[0x300] = 0x80;
+        c.registers[0] = 64 + 5;
+        c.registers[1] = 32 + 6;
+        run(&mut c, 2);
+        assert!(c.screen.get_pixel((5, 6)));
+    }
+
+    #[test]
+    fn draw_wraps_or_clips_at_edge() {
+        let program = [0xA300, 0xD012];
+
+        let mut c = chip8(&program);
+        c.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+        c.registers[0] = 60;
+        c.registers[1] = 31;
+        run(&mut c, 2);
+        assert!(c.screen.get_pixel((63, 31)));
+        assert!(c.screen.get_pixel((0, 31)));
+        assert!(c.screen.get_pixel((0, 0)));
+
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &program);
+        c.vblank();
+        c.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+        c.registers[0] = 60;
+        c.registers[1] = 31;
+        run(&mut c, 2);
+        assert!(c.screen.get_pixel((63, 31)));
+        assert!(!c.screen.get_pixel((0, 31)));
+        assert!(!c.screen.get_pixel((63, 0)));
+    }
+
+    #[test]
+    fn draw_large_sprite_in_hires() {
+        let mut c = chip8(&[0x00FF, 0xA300, 0xD010]);
+        c.memory[0x300..0x320].fill(0xFF);
+        c.registers[0] = 100;
+        c.registers[1] = 40;
+        run(&mut c, 3);
+        assert!(c.screen.get_pixel((100, 40)));
+        assert!(c.screen.get_pixel((115, 55)));
+        assert!(!c.screen.get_pixel((116, 55)));
+        assert!(!c.screen.get_pixel((115, 56)));
+    }
+
+    #[test]
+    fn display_wait() {
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0xD011, 0xD011]);
+        c.vblank();
+        run(&mut c, 1);
+        assert_eq!(c.step(), Ok(StepOutcome::WaitingForVblank));
+        assert_eq!(c.program_counter, 0x202);
+        c.vblank();
+        run(&mut c, 1);
+    }
+
+    #[test]
+    fn key_skips() {
+        let mut c = chip8(&[0xE09E, 0x0000, 0xE0A1]);
+        c.registers[0] = 0xA;
+        c.key_press(0xA);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x204);
+        run(&mut c, 1);
+        assert_eq!(c.program_counter, 0x206);
+    }
+
+    #[test]
+    fn invalid_key() {
+        let mut c = chip8(&[0xE09E]);
+        c.registers[0] = 0x10;
+        assert_eq!(c.step(), Err(Chip8Error::InvalidKey { pc: 0x200, key: 0x10 }));
+    }
+
+    #[test]
+    fn wait_for_key() {
+        let mut c = chip8(&[0xF30A]);
+        assert_eq!(c.step(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(c.program_counter, 0x200);
+        assert!(c.is_waiting_for_key());
+        c.key_press(0x7);
+        assert_eq!(c.step(), Ok(StepOutcome::WaitingForKey));
+        c.key_release(0x7);
+        run(&mut c, 1);
+        assert_eq!(c.registers[3], 0x7);
+        assert!(!c.is_waiting_for_key());
+    }
+
+    #[test]
+    fn wait_for_key_on_press() {
+        let quirks = Quirks { wait_for_release: false, ..Quirks::MODERN };
+        let mut c = chip8_with(quirks, &[0xF30A, 0xF40A]);
+        c.key_press(0x7);
+        run(&mut c, 1);
+        assert_eq!(c.registers[3], 0x7);
+        // sigue apretada en el frame siguiente: no cuenta de nuevo
+        c.tick();
+        assert_eq!(c.step(), Ok(StepOutcome::WaitingForKey));
+        c.key_release(0x7);
+        c.key_press(0x2);
+        run(&mut c, 1);
+        assert_eq!(c.registers[4], 0x2);
+    }
+
+    #[test]
+    fn key_edges_last_a_frame() {
+        let mut c = chip8(&[]);
+        c.key_press(0x3);
+        c.key_press(0x3);
+        assert!(c.key_just_pressed(0x3));
+        c.tick();
+        assert!(!c.key_just_pressed(0x3));
+        c.key_release(0x3);
+        assert!(c.key_just_released(0x3));
+    }
+
+    #[test]
+    fn timers() {
+        let mut c = chip8(&[0xF015, 0xF118, 0xF207]);
+        c.registers[0] = 10;
+        c.registers[1] = 20;
+        run(&mut c, 2);
+        c.decrease_delay_timer();
+        c.decrease_sound_timer();
+        run(&mut c, 1);
+        assert_eq!(c.registers[2], 9);
+        assert_eq!(c.sound_timer(), 19);
+    }
+
+    #[test]
+    fn bcd() {
+        for (value, digits) in [(0, [0, 0, 0]), (9, [0, 0, 9]), (128, [1, 2, 8]), (255, [2, 5, 5])] {
+            let mut c = chip8(&[0xA300, 0xF033]);
+            c.registers[0] = value;
+            run(&mut c, 2);
+            assert_eq!(c.memory[0x300..0x303], digits, "{}", value);
+        }
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let mut c = chip8(&[0xA300, 0xF255, 0xA300, 0xF565]);
+        c.registers[..3].copy_from_slice(&[1, 2, 3]);
+        c.memory[0x303..0x306].copy_from_slice(&[4, 5, 6]);
+        run(&mut c, 4);
+        assert_eq!(c.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(c.registers[..6], [1, 2, 3, 4, 5, 6]);
+        assert_eq!(c.index, 0x300);
+
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0xA300, 0xF255]);
+        run(&mut c, 2);
+        assert_eq!(c.index, 0x303);
+    }
+
+    #[test]
+    fn save_and_load_register_range() {
+        // 5xy2/5xy3 en orden inverso si X > Y
+        let mut c = chip8(&[0xA300, 0x5312, 0xA300, 0x5763]);
+        c.registers[1..4].copy_from_slice(&[1, 2, 3]);
+        run(&mut c, 4);
+        assert_eq!(c.memory[0x300..0x303], [3, 2, 1]);
+        assert_eq!(c.registers[6..8], [2, 3]);
+    }
+
+    #[test]
+    fn rpl_flags() {
+        let mut c = chip8(&[0xF275, 0x6000, 0x6100, 0x6200, 0xF285]);
+        c.registers[..3].copy_from_slice(&[7, 8, 9]);
+        run(&mut c, 5);
+        assert_eq!(c.registers[..3], [7, 8, 9]);
+    }
+
+    #[test]
+    fn scroll_down() {
+        let mut c = chip8(&[0x00C2]);
+        c.screen.set_pixel((1, 1), 1, true);
+        run(&mut c, 1);
+        assert!(!c.screen.get_pixel((1, 1)));
+        assert!(c.screen.get_pixel((1, 3)));
+    }
+
+    #[test]
+    fn exit() {
+        let mut c = chip8(&[0x00FD]);
+        assert_eq!(c.step(), Ok(StepOutcome::Exited));
+        assert_eq!(c.program_counter, 0x200);
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut c = chip8(&[0x5001]);
+        assert_eq!(c.step(), Err(Chip8Error::UnknownOpcode { pc: 0x200, opcode: 0x5001 }));
+    }
+
+    #[test]
+    fn timers_run_at_60_hz() {
+        let mut c = chip8(&[0x1200]);
+        c.delay_timer = 60;
+        for _ in 0..1000 {
+            c.run_for(Duration::from_millis(1)).unwrap();
+        }
+        assert_eq!(c.delay_timer, 0);
+    }
 }
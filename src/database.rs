@@ -0,0 +1,289 @@
+use crate::quirks::{QuirkProfile, Quirks};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Configuración por ROM, buscada por el SHA-1 de sus bytes. Lee el formato
+// de https://github.com/chip-8/chip-8-database: un directorio con
+// programs.json (los programas, cada uno con sus ROMs por hash) y
+// sha1-hashes.json (hash -> índice en programs.json).
+//
+// El archivo del usuario tiene el mismo formato que las ROMs de
+// programs.json, por hash, y lo que dice pisa a la base de datos:
+//
+//     {
+//         "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12": {
+//             "platforms": ["superchip"],
+//             "tickrate": 30,
+//             "colors": { "pixels": ["#000000", "#ffcc00"] },
+//             "keys": { "up": 5, "down": 8, "a": 6 }
+//         }
+//     }
+
+const PROGRAMS: &str = "programs.json";
+const HASHES: &str = "sha1-hashes.json";
+
+#[derive(Debug, Error)]
+pub enum DatabaseError {
+    #[error("{path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{path}: {source}")]
+    Json { path: PathBuf, source: serde_json::Error },
+    #[error("ROM {hash} points to program {index}, which doesn't exist")]
+    BadIndex { hash: String, index: usize },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Program {
+    title: Option<String>,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    title: Option<String>,
+    #[serde(default)]
+    platforms: Vec<String>,
+    // quirks que difieren de las de la plataforma
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkFlags>,
+    // instrucciones por frame
+    tickrate: Option<u32>,
+    start_address: Option<u16>,
+    colors: Option<Colors>,
+    keys: Option<HashMap<String, u8>>,
+}
+
+// los nombres de chip-8-database, cada uno es true si la plataforma tiene
+// ese comportamiento
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+// lo que se sabe de una ROM, lo que falta queda como lo configuró el usuario
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: Option<String>,
+    // id de plataforma de chip-8-database, p. ej. "superchip"
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    pub ips: Option<u32>,
+    pub load_address: Option<u16>,
+    // "#RRGGBB", desde el color 0
+    pub colors: Vec<String>,
+    // "up", "down", "left", "right", "a", "b" -> tecla del CHIP-8
+    pub keys: HashMap<String, u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    overrides: HashMap<String, RomEntry>,
+}
+
+pub fn rom_hash(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+// ~/.local/share/chip-8/database y ~/.config/chip-8/roms.json
+pub fn default_dir() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    Some(home.join(".local/share/chip-8/database"))
+}
+
+pub fn default_overrides() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    Some(home.join(".config/chip-8/roms.json"))
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, DatabaseError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|source| DatabaseError::Io { path: path.to_path_buf(), source })?;
+    serde_json::from_str(&text)
+        .map_err(|source| DatabaseError::Json { path: path.to_path_buf(), source })
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    pub fn load_dir(dir: &Path) -> Result<Database, DatabaseError> {
+        let mut db = Database::new();
+        db.programs = read_json(&dir.join(PROGRAMS))?;
+        db.hashes = read_json(&dir.join(HASHES))?;
+
+        if let Some((hash, &index)) = db.hashes.iter().find(|(_, &i)| i >= db.programs.len()) {
+            return Err(DatabaseError::BadIndex { hash: hash.clone(), index });
+        }
+
+        Ok(db)
+    }
+
+    pub fn load_overrides(&mut self, path: &Path) -> Result<(), DatabaseError> {
+        let overrides: HashMap<String, RomEntry> = read_json(path)?;
+        self.overrides
+            .extend(overrides.into_iter().map(|(hash, rom)| (hash.to_ascii_lowercase(), rom)));
+        Ok(())
+    }
+
+    pub fn lookup(&self, hash: &str) -> Option<RomInfo> {
+        let hash = hash.to_ascii_lowercase();
+        let program = self.hashes.get(&hash).map(|&i| &self.programs[i]);
+        let rom = program.and_then(|p| p.roms.get(&hash));
+        let user = self.overrides.get(&hash);
+
+        if rom.is_none() && user.is_none() {
+            return None;
+        }
+
+        let rom = merge(rom.cloned().unwrap_or_default(), user.cloned().unwrap_or_default());
+        let platform = rom.platforms.iter().find(|p| profile(p).is_some()).cloned();
+        let quirks = platform.as_deref().and_then(|id| {
+            let quirks = profile(id)?.quirks();
+            Some(match rom.quirky_platforms.get(id) {
+                Some(flags) => flags.apply(quirks),
+                None => quirks,
+            })
+        });
+
+        Some(RomInfo {
+            title: rom.title.or_else(|| program.and_then(|p| p.title.clone())),
+            platform,
+            quirks,
+            ips: rom.tickrate.map(|t| t.saturating_mul(60)),
+            load_address: rom.start_address,
+            colors: rom.colors.map(|c| c.pixels).unwrap_or_default(),
+            keys: rom.keys.unwrap_or_default(),
+        })
+    }
+}
+
+// lo que el usuario pone pisa a la base de datos
+fn merge(db: RomEntry, user: RomEntry) -> RomEntry {
+    let mut quirky_platforms = db.quirky_platforms;
+    quirky_platforms.extend(user.quirky_platforms);
+
+    RomEntry {
+        title: user.title.or(db.title),
+        platforms: if user.platforms.is_empty() { db.platforms } else { user.platforms },
+        quirky_platforms,
+        tickrate: user.tickrate.or(db.tickrate),
+        start_address: user.start_address.or(db.start_address),
+        colors: user.colors.or(db.colors),
+        keys: user.keys.or(db.keys),
+    }
+}
+
+// las plataformas de chip-8-database que se pueden emular
+fn profile(platform: &str) -> Option<QuirkProfile> {
+    match platform {
+        "originalChip8" | "hybridVIP" | "chip8x" => Some(QuirkProfile::CosmacVip),
+        "modernChip8" => Some(QuirkProfile::Modern),
+        "chip48" => Some(QuirkProfile::Chip48),
+        "superchip1" | "superchip" => Some(QuirkProfile::SuperChip),
+        "xochip" => Some(QuirkProfile::XoChip),
+        _ => None,
+    }
+}
+
+impl QuirkFlags {
+    fn apply(self, mut quirks: Quirks) -> Quirks {
+        if let Some(shift) = self.shift {
+            quirks.shift_uses_vy = !shift;
+        }
+        // acá no se distingue I += X de I += X + 1
+        if let Some(unchanged) = self.memory_leave_i_unchanged {
+            quirks.load_store_increments_index = !unchanged;
+        }
+        if self.memory_increment_by_x == Some(true) {
+            quirks.load_store_increments_index = true;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.clip_sprites = !wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_uses_vx = jump;
+        }
+        if let Some(vblank) = self.vblank {
+            quirks.display_wait = vblank;
+        }
+        if let Some(logic) = self.logic {
+            quirks.logic_resets_vf = logic;
+        }
+        quirks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn database() -> Database {
+        let programs = format!(r##"[
+            {{ "title": "Other", "roms": {{}} }},
+            {{
+                "title": "Game",
+                "roms": {{
+                    "{HASH}": {{
+                        "file": "game.ch8",
+                        "platforms": ["megachip8", "superchip"],
+                        "quirkyPlatforms": {{ "superchip": {{ "wrap": true }} }},
+                        "tickrate": 30,
+                        "colors": {{ "pixels": ["#000000", "#ff0000"] }},
+                        "keys": {{ "up": 5, "a": 6 }}
+                    }}
+                }}
+            }}
+        ]"##);
+        Database {
+            programs: serde_json::from_str(&programs).unwrap(),
+            hashes: HashMap::from([(HASH.to_string(), 1)]),
+            overrides: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn lookup_uses_the_first_known_platform() {
+        let info = database().lookup(&HASH.to_ascii_uppercase()).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Game"));
+        assert_eq!(info.platform.as_deref(), Some("superchip"));
+        assert_eq!(info.quirks, Some(Quirks { clip_sprites: false, ..Quirks::SUPER_CHIP }));
+        assert_eq!(info.ips, Some(30 * 60));
+        assert_eq!(info.colors, ["#000000", "#ff0000"]);
+        assert_eq!(info.keys["up"], 5);
+        assert_eq!(database().lookup("ffff"), None);
+    }
+
+    #[test]
+    fn overrides_win() {
+        let mut db = database();
+        let user = r#"{ "platforms": ["xochip"], "tickrate": 1000 }"#;
+        db.overrides.insert(HASH.to_string(), serde_json::from_str(user).unwrap());
+
+        let info = db.lookup(HASH).unwrap();
+        assert_eq!(info.quirks, Some(Quirks::XO_CHIP));
+        assert_eq!(info.ips, Some(60_000));
+        assert_eq!(info.keys["a"], 6);
+    }
+}
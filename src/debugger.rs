@@ -0,0 +1,674 @@
+use crate::chip8::{Chip8, Chip8Error, MemoryAccess, StepOutcome};
+use crate::disassembler::disassemble_at;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: MemoryAccess) -> bool {
+        match (self.kind, access) {
+            (WatchKind::Read | WatchKind::Access, MemoryAccess::Read(addr)) |
+            (WatchKind::Write | WatchKind::Access, MemoryAccess::Write(addr)) => addr == self.addr,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::Access => "rw",
+        };
+        write!(f, "{:#05x} {}", self.addr, kind)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+// frena cuando VX cumple la comparación (sólo en el step en que pasa a cumplirla)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: usize,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+impl Condition {
+    fn holds(&self, registers: &[u8]) -> bool {
+        let vx = registers[self.register];
+
+        match self.comparison {
+            Comparison::Eq => vx == self.value,
+            Comparison::Ne => vx != self.value,
+            Comparison::Lt => vx < self.value,
+            Comparison::Le => vx <= self.value,
+            Comparison::Gt => vx > self.value,
+            Comparison::Ge => vx >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:X} {} {:#04x}", self.register, self.comparison.symbol(), self.value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakReason {
+    User,
+    Step,
+    Returned,
+    Breakpoint(u16),
+    Watchpoint(MemoryAccess),
+    Condition(Condition),
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakReason::User => write!(f, "paused"),
+            BreakReason::Step => write!(f, "step"),
+            BreakReason::Returned => write!(f, "returned"),
+            BreakReason::Breakpoint(pc) => write!(f, "breakpoint at {:#05x}", pc),
+            BreakReason::Watchpoint(MemoryAccess::Read(addr)) => {
+                write!(f, "watchpoint: read {:#05x}", addr)
+            },
+            BreakReason::Watchpoint(MemoryAccess::Write(addr)) => {
+                write!(f, "watchpoint: write {:#05x}", addr)
+            },
+            BreakReason::Condition(c) => write!(f, "condition {}", c),
+        }
+    }
+}
+
+enum RunMode {
+    Continue,
+    // quedan n instrucciones
+    Step(usize),
+    // 2nnn: hasta volver a la instrucción siguiente al mismo nivel del stack
+    StepOver { depth: usize, return_pc: u16 },
+    RunToReturn { depth: usize },
+}
+
+pub struct Debugger {
+    paused: bool,
+    reason: Option<BreakReason>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<Condition>,
+    // si cada condición se cumplía en el step anterior
+    condition_state: Vec<bool>,
+    mode: RunMode,
+    // al continuar no se frena en el breakpoint donde estaba parado
+    // mientras el PC siga ahí
+    resume_pc: Option<u16>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            paused: false,
+            reason: None,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+            condition_state: Vec::new(),
+            mode: RunMode::Continue,
+            resume_pc: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn reason(&self) -> Option<BreakReason> {
+        self.reason
+    }
+
+    pub fn pause(&mut self) {
+        self.stop(BreakReason::User);
+    }
+
+    pub fn resume(&mut self, chip8: &Chip8) {
+        self.paused = false;
+        self.reason = None;
+        self.resume_pc = Some(chip8.program_counter());
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+        self.condition_state.push(false);
+    }
+
+    fn stop(&mut self, reason: BreakReason) {
+        if !self.paused {
+            self.paused = true;
+            self.reason = Some(reason);
+            self.mode = RunMode::Continue;
+        }
+    }
+
+    // ejecuta una instrucción si no hay que frenar antes; None si frenó sin
+    // ejecutarla. Si frena después de ejecutarla devuelve Some, y el
+    // próximo step devuelve None.
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<Option<StepOutcome>, Chip8Error> {
+        if self.paused {
+            return Ok(None);
+        }
+
+        // Fx0A y Dxyn con display_wait se repiten sin mover el PC, así que el
+        // breakpoint donde se continuó no cuenta hasta que el PC cambie
+        let pc = chip8.program_counter();
+        if self.resume_pc != Some(pc) {
+            self.resume_pc = None;
+            if self.breakpoints.contains(&pc) {
+                self.stop(BreakReason::Breakpoint(pc));
+                return Ok(None);
+            }
+        }
+
+        chip8.set_memory_tracking(!self.watchpoints.is_empty());
+        let outcome = chip8.step()?;
+
+        let hit = chip8.memory_accesses()
+            .iter()
+            .find(|&&a| self.watchpoints.iter().any(|w| w.matches(a)));
+        if let Some(&access) = hit {
+            self.stop(BreakReason::Watchpoint(access));
+        }
+
+        let mut triggered = None;
+        for (condition, state) in self.conditions.iter().zip(self.condition_state.iter_mut()) {
+            let holds = condition.holds(chip8.registers());
+            if holds && !*state && triggered.is_none() {
+                triggered = Some(*condition);
+            }
+            *state = holds;
+        }
+        if let Some(condition) = triggered {
+            self.stop(BreakReason::Condition(condition));
+        }
+
+        match self.mode {
+            RunMode::Continue => {},
+            RunMode::Step(n) if n <= 1 => self.stop(BreakReason::Step),
+            RunMode::Step(n) => self.mode = RunMode::Step(n - 1),
+            RunMode::StepOver { depth, return_pc } => {
+                if chip8.program_counter() == return_pc && chip8.stack_depth() <= depth {
+                    self.stop(BreakReason::Step);
+                }
+            },
+            RunMode::RunToReturn { depth } => {
+                if chip8.stack_depth() < depth {
+                    self.stop(BreakReason::Returned);
+                }
+            },
+        }
+
+        Ok(Some(outcome))
+    }
+
+    // devuelve lo que hay que mostrar
+    pub fn execute(&mut self, command: Command, chip8: &Chip8) -> String {
+        let mut out = String::new();
+
+        match command {
+            Command::Continue => self.resume(chip8),
+            Command::Step(n) => {
+                self.resume(chip8);
+                self.mode = RunMode::Step(n);
+            },
+            Command::Next => {
+                let pc = chip8.program_counter();
+                self.resume(chip8);
+                self.mode = match chip8.opcode_at(pc) {
+                    Ok(opcode) if opcode & 0xF000 == 0x2000 => RunMode::StepOver {
+                        depth: chip8.stack_depth(),
+                        return_pc: pc.wrapping_add(2),
+                    },
+                    _ => RunMode::Step(1),
+                };
+            },
+            Command::Finish => {
+                if chip8.stack_depth() == 0 {
+                    out.push_str("not inside a subroutine");
+                } else {
+                    self.resume(chip8);
+                    self.mode = RunMode::RunToReturn { depth: chip8.stack_depth() };
+                }
+            },
+            Command::Break(addr) => {
+                self.add_breakpoint(addr);
+                let _ = write!(out, "breakpoint at {:#05x}", addr);
+            },
+            Command::Watch(watchpoint) => {
+                self.add_watchpoint(watchpoint);
+                let _ = write!(out, "watchpoint {}", watchpoint);
+            },
+            Command::Condition(condition) => {
+                self.add_condition(condition);
+                let _ = write!(out, "condition {}", condition);
+            },
+            Command::Delete(addr) => {
+                let removed = self.breakpoints.remove(&addr);
+                let watchpoints = self.watchpoints.len();
+                self.watchpoints.retain(|w| w.addr != addr as usize);
+
+                if !removed && watchpoints == self.watchpoints.len() {
+                    let _ = write!(out, "nothing at {:#05x}", addr);
+                }
+            },
+            Command::ClearConditions => {
+                self.conditions.clear();
+                self.condition_state.clear();
+            },
+            Command::List => {
+                for addr in &self.breakpoints {
+                    let _ = writeln!(out, "break {:#05x}", addr);
+                }
+                for watchpoint in &self.watchpoints {
+                    let _ = writeln!(out, "watch {}", watchpoint);
+                }
+                for condition in &self.conditions {
+                    let _ = writeln!(out, "cond {}", condition);
+                }
+                out.truncate(out.trim_end().len());
+            },
+            Command::Registers => out = registers(chip8),
+            Command::Memory { addr, len } => out = memory(chip8, addr, len),
+            Command::Help => out.push_str(HELP),
+            // lo maneja el frontend
+            Command::Quit => {},
+        }
+
+        out
+    }
+}
+
+pub fn registers(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    let pc = chip8.program_counter();
+
+    let _ = writeln!(
+        out,
+        "PC={:#06x} I={:#06x} SP={} DT={:02x} ST={:02x}",
+        pc,
+        chip8.index(),
+        chip8.stack_depth(),
+        chip8.delay_timer(),
+        chip8.sound_timer()
+    );
+    if chip8.is_waiting_for_key() {
+        out.push_str("waiting for a key (Fx0A)\n");
+    }
+    let registers: Vec<String> = chip8.registers()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{:X}={:02x}", i, v))
+        .collect();
+    out.push_str(&registers.join(" "));
+    if let Some(instruction) = disassemble_at(chip8.memory(), pc as usize) {
+        let opcode = chip8.opcode_at(pc).unwrap_or_default();
+        let _ = write!(out, "\n{:#06x}: {:04x}  {}", pc, opcode, instruction);
+    }
+
+    out
+}
+
+fn memory(chip8: &Chip8, addr: usize, len: usize) -> String {
+    let mut out = String::new();
+    let memory = chip8.memory();
+    let end = addr.saturating_add(len).min(memory.len());
+
+    for (row, bytes) in memory[addr.min(end)..end].chunks(16).enumerate() {
+        let _ = write!(out, "{:#06x}:", addr + row * 16);
+        for byte in bytes {
+            let _ = write!(out, " {:02x}", byte);
+        }
+        out.push('\n');
+    }
+    out.truncate(out.trim_end().len());
+
+    out
+}
+
+const HELP: &str = "\
+c, continue          resume execution
+s, step [n]          execute n instructions (default 1)
+n, next              step over 2nnn calls
+f, finish            run until the current subroutine returns
+b, break ADDR        breakpoint at ADDR
+w, watch ADDR [r|w|rw]  break on memory access
+cond VX OP VAL       break when VX OP VAL becomes true (OP: == != < <= > >=)
+uncond               remove all conditions
+d, delete ADDR       remove breakpoints and watchpoints at ADDR
+l, list              list breakpoints, watchpoints and conditions
+r, regs              show registers
+m, mem ADDR [LEN]    dump memory
+q, quit              quit the emulator
+numbers are hexadecimal (0x prefix optional)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Step(usize),
+    Next,
+    Finish,
+    Break(u16),
+    Watch(Watchpoint),
+    Condition(Condition),
+    ClearConditions,
+    Delete(u16),
+    List,
+    Registers,
+    Memory { addr: usize, len: usize },
+    Help,
+    Quit,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("empty command")]
+    Empty,
+    #[error("unknown command '{0}', try 'help'")]
+    Unknown(String),
+    #[error("missing {0}")]
+    Missing(&'static str),
+    #[error("invalid {0} '{1}'")]
+    Invalid(&'static str, String),
+}
+
+fn parse_number(s: Option<&str>, what: &'static str) -> Result<usize, CommandError> {
+    let s = s.ok_or(CommandError::Missing(what))?;
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+
+    usize::from_str_radix(digits, 16).map_err(|_| CommandError::Invalid(what, s.to_string()))
+}
+
+fn parse_address(s: Option<&str>) -> Result<u16, CommandError> {
+    let addr = parse_number(s, "address")?;
+    u16::try_from(addr).map_err(|_| CommandError::Invalid("address", format!("{:#x}", addr)))
+}
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().ok_or(CommandError::Empty)?;
+
+        let command = match name {
+            "c" | "continue" => Command::Continue,
+            "s" | "step" => match words.next() {
+                Some(n) => Command::Step(
+                    n.parse().map_err(|_| CommandError::Invalid("count", n.to_string()))?
+                ),
+                None => Command::Step(1),
+            },
+            "n" | "next" => Command::Next,
+            "f" | "finish" => Command::Finish,
+            "b" | "break" => Command::Break(parse_address(words.next())?),
+            "w" | "watch" => {
+                let addr = parse_address(words.next())? as usize;
+                let kind = match words.next() {
+                    Some("r") => WatchKind::Read,
+                    Some("w") => WatchKind::Write,
+                    Some("rw") | None => WatchKind::Access,
+                    Some(k) => return Err(CommandError::Invalid("access kind", k.to_string())),
+                };
+                Command::Watch(Watchpoint { addr, kind })
+            },
+            "cond" => {
+                let reg = words.next().ok_or(CommandError::Missing("register"))?;
+                let register = reg
+                    .strip_prefix(['V', 'v'])
+                    .and_then(|r| usize::from_str_radix(r, 16).ok())
+                    .filter(|&r| r < 16)
+                    .ok_or_else(|| CommandError::Invalid("register", reg.to_string()))?;
+
+                let comparison = match words.next() {
+                    Some("==") => Comparison::Eq,
+                    Some("!=") => Comparison::Ne,
+                    Some("<") => Comparison::Lt,
+                    Some("<=") => Comparison::Le,
+                    Some(">") => Comparison::Gt,
+                    Some(">=") => Comparison::Ge,
+                    Some(op) => return Err(CommandError::Invalid("comparison", op.to_string())),
+                    None => return Err(CommandError::Missing("comparison")),
+                };
+
+                let value = parse_number(words.next(), "value")?;
+                let value = u8::try_from(value)
+                    .map_err(|_| CommandError::Invalid("value", format!("{:#x}", value)))?;
+
+                Command::Condition(Condition { register, comparison, value })
+            },
+            "uncond" => Command::ClearConditions,
+            "d" | "delete" => Command::Delete(parse_address(words.next())?),
+            "l" | "list" => Command::List,
+            "r" | "regs" => Command::Registers,
+            "m" | "mem" => {
+                let addr = parse_number(words.next(), "address")?;
+                let len = match words.next() {
+                    Some(len) => parse_number(Some(len), "length")?,
+                    None => 0x40,
+                };
+                Command::Memory { addr, len }
+            },
+            "h" | "help" | "?" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(CommandError::Unknown(name.to_string())),
+        };
+
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::FRAME;
+    use crate::quirks::Quirks;
+
+    fn chip8_with(quirks: Quirks, program: &[u16]) -> Chip8 {
+        let mut c = Chip8::with_quirks(quirks);
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        c.load_rom_bytes(&rom).unwrap();
+        c
+    }
+
+    fn chip8(program: &[u16]) -> Chip8 {
+        chip8_with(Quirks::MODERN, program)
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut c = chip8(&[0x6001, 0x6102, 0x1204]);
+        let mut d = Debugger::new();
+        d.execute("b 202".parse().unwrap(), &c);
+
+        assert_eq!(d.step(&mut c), Ok(Some(StepOutcome::Executed)));
+        assert_eq!(d.step(&mut c), Ok(None));
+        assert_eq!(d.reason(), Some(BreakReason::Breakpoint(0x202)));
+        assert_eq!(d.step(&mut c), Ok(None));
+        assert_eq!(c.registers()[1], 0);
+
+        // sigue desde el breakpoint sin volver a frenar ahí
+        d.execute(Command::Continue, &c);
+        assert_eq!(d.step(&mut c), Ok(Some(StepOutcome::Executed)));
+        assert_eq!(c.registers()[1], 2);
+        assert!(!d.is_paused());
+    }
+
+    #[test]
+    fn continue_past_a_waiting_breakpoint() {
+        let mut c = chip8(&[0xF00A, 0x1200]);
+        let mut d = Debugger::new();
+        d.add_breakpoint(0x200);
+        assert_eq!(d.step(&mut c), Ok(None));
+
+        d.resume(&c);
+        for _ in 0..3 {
+            assert_eq!(d.step(&mut c), Ok(Some(StepOutcome::WaitingForKey)));
+        }
+        c.key_press(1);
+        c.key_release(1);
+        assert_eq!(d.step(&mut c), Ok(Some(StepOutcome::Executed)));
+        assert_eq!(c.program_counter(), 0x202);
+
+        // el PC cambió, el breakpoint vuelve a valer
+        assert_eq!(d.step(&mut c), Ok(Some(StepOutcome::Executed)));
+        assert_eq!(d.step(&mut c), Ok(None));
+        assert_eq!(d.reason(), Some(BreakReason::Breakpoint(0x200)));
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut c = chip8(&[0xA300, 0x6089, 0xF033, 0xF265]);
+        let mut d = Debugger::new();
+        d.execute("w 301 w".parse().unwrap(), &c);
+        d.execute("watch 0x302 r".parse().unwrap(), &c);
+
+        for _ in 0..3 {
+            assert!(d.step(&mut c).unwrap().is_some());
+        }
+        assert_eq!(d.reason(), Some(BreakReason::Watchpoint(MemoryAccess::Write(0x301))));
+
+        d.resume(&c);
+        assert!(d.step(&mut c).unwrap().is_some());
+        assert_eq!(d.reason(), Some(BreakReason::Watchpoint(MemoryAccess::Read(0x302))));
+
+        d.execute(Command::Delete(0x301), &c);
+        d.execute(Command::Delete(0x302), &c);
+        assert_eq!(d.execute(Command::List, &c), "");
+    }
+
+    #[test]
+    fn conditions() {
+        let mut c = chip8(&[0x7001, 0x1200]);
+        let mut d = Debugger::new();
+        d.execute("cond V0 >= 3".parse().unwrap(), &c);
+
+        while d.step(&mut c).unwrap().is_some() {}
+        assert_eq!(c.registers()[0], 3);
+        assert!(matches!(d.reason(), Some(BreakReason::Condition(_))));
+
+        // sólo frena cuando pasa a cumplirse
+        d.resume(&c);
+        for _ in 0..10 {
+            assert!(d.step(&mut c).unwrap().is_some());
+        }
+    }
+
+    // cada instrucción que se ejecuta paso a paso cuenta para el scheduler
+    #[test]
+    fn stepping_runs_the_timers() {
+        let mut c = chip8_with(Quirks::COSMAC_VIP, &[0x6005, 0xF015, 0xD011, 0x1206]);
+        let mut d = Debugger::new();
+        d.pause();
+
+        let ips = c.instructions_per_second() as usize;
+        for _ in 0..2 * ips / 60 {
+            d.execute(Command::Step(1), &c);
+            c.run_for_with(FRAME, |c| d.step(c)).unwrap();
+            assert!(d.is_paused());
+        }
+        assert!(c.delay_timer() < 5);
+        assert_eq!(c.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn parse_commands() {
+        let parse = |s: &str| s.parse::<Command>();
+        assert_eq!(parse("s"), Ok(Command::Step(1)));
+        assert_eq!(parse("step 10"), Ok(Command::Step(10)));
+        assert_eq!(parse("b 0x2A0"), Ok(Command::Break(0x2A0)));
+        assert_eq!(
+            parse("w 300"),
+            Ok(Command::Watch(Watchpoint { addr: 0x300, kind: WatchKind::Access }))
+        );
+        assert_eq!(
+            parse("cond vA != ff"),
+            Ok(Command::Condition(Condition {
+                register: 0xA,
+                comparison: Comparison::Ne,
+                value: 0xFF,
+            }))
+        );
+        assert_eq!(parse("m 200"), Ok(Command::Memory { addr: 0x200, len: 0x40 }));
+
+        assert_eq!(parse(" "), Err(CommandError::Empty));
+        assert_eq!(parse("jump"), Err(CommandError::Unknown("jump".to_string())));
+        assert_eq!(parse("b"), Err(CommandError::Missing("address")));
+        assert_eq!(parse("b 10000"), Err(CommandError::Invalid("address", "0x10000".to_string())));
+        assert_eq!(parse("w 300 x"), Err(CommandError::Invalid("access kind", "x".to_string())));
+        assert_eq!(parse("cond VG == 1"), Err(CommandError::Invalid("register", "VG".to_string())));
+        let value = CommandError::Invalid("value", "0x100".to_string());
+        assert_eq!(parse("cond V0 == 100"), Err(value));
+    }
+
+    #[test]
+    fn memory_dump() {
+        let c = chip8(&[0x1234, 0x5678]);
+        assert_eq!(memory(&c, 0x200, 4), "0x0200: 12 34 56 78");
+        assert_eq!(memory(&c, usize::MAX, 1), "");
+
+        let mut d = Debugger::new();
+        let command = "m ffffffffffffffff 1".parse().unwrap();
+        assert_eq!(d.execute(command, &c), "");
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+// Una instrucción decodificada. Los registros son el número (0x0-0xF).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    ScrollDown(u8),
+    ScrollUp(u8),
+    Clear,
+    Return,
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    Jump(u16),
+    Call(u16),
+    SkipEqualByte(u8, u8),
+    SkipNotEqualByte(u8, u8),
+    SkipEqual(u8, u8),
+    SaveRange(u8, u8),
+    LoadRange(u8, u8),
+    SetByte(u8, u8),
+    AddByte(u8, u8),
+    Set(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    Add(u8, u8),
+    Sub(u8, u8),
+    ShiftRight(u8, u8),
+    SubReverse(u8, u8),
+    ShiftLeft(u8, u8),
+    SkipNotEqual(u8, u8),
+    SetIndex(u16),
+    JumpOffset(u16),
+    Random(u8, u8),
+    Draw(u8, u8, u8),
+    SkipKey(u8),
+    SkipNotKey(u8),
+    // F000 sola, sin la dirección que viene en los 2 bytes siguientes
+    LongPrefix,
+    SetIndexLong(u16),
+    Planes(u8),
+    Audio,
+    GetDelay(u8),
+    WaitKey(u8),
+    SetDelay(u8),
+    SetSound(u8),
+    AddIndex(u8),
+    Font(u8),
+    BigFont(u8),
+    Bcd(u8),
+    Pitch(u8),
+    Store(u8),
+    Restore(u8),
+    SaveFlags(u8),
+    LoadFlags(u8),
+    // cualquier cosa que no es una instrucción conocida
+    Data(u16),
+}
+
+// el único decodificador, también lo usa Chip8::execute
+#[allow(non_snake_case)]
+pub fn disassemble(opcode: u16) -> Instruction {
+    let nibbles = [
+        (opcode >> 12) as u8,
+        (opcode >> 8 & 0xF) as u8,
+        (opcode >> 4 & 0xF) as u8,
+        (opcode & 0xF) as u8,
+    ];
+    let address = opcode & 0xFFF;
+    let byte_2 = (opcode & 0xFF) as u8;
+
+    match nibbles {
+        [0x0, 0x0, 0x0, 0x0] => Instruction::Nop,
+        [0x0, 0x0, 0xC, N] => Instruction::ScrollDown(N),
+        [0x0, 0x0, 0xD, N] => Instruction::ScrollUp(N),
+        [0x0, 0x0, 0xE, 0x0] => Instruction::Clear,
+        [0x0, 0x0, 0xE, 0xE] => Instruction::Return,
+        [0x0, 0x0, 0xF, 0xB] => Instruction::ScrollRight,
+        [0x0, 0x0, 0xF, 0xC] => Instruction::ScrollLeft,
+        [0x0, 0x0, 0xF, 0xD] => Instruction::Exit,
+        [0x0, 0x0, 0xF, 0xE] => Instruction::LowRes,
+        [0x0, 0x0, 0xF, 0xF] => Instruction::HighRes,
+        [0x1, _, _, _] => Instruction::Jump(address),
+        [0x2, _, _, _] => Instruction::Call(address),
+        [0x3, X, _, _] => Instruction::SkipEqualByte(X, byte_2),
+        [0x4, X, _, _] => Instruction::SkipNotEqualByte(X, byte_2),
+        [0x5, X, Y, 0x0] => Instruction::SkipEqual(X, Y),
+        [0x5, X, Y, 0x2] => Instruction::SaveRange(X, Y),
+        [0x5, X, Y, 0x3] => Instruction::LoadRange(X, Y),
+        [0x6, X, _, _] => Instruction::SetByte(X, byte_2),
+        [0x7, X, _, _] => Instruction::AddByte(X, byte_2),
+        [0x8, X, Y, 0x0] => Instruction::Set(X, Y),
+        [0x8, X, Y, 0x1] => Instruction::Or(X, Y),
+        [0x8, X, Y, 0x2] => Instruction::And(X, Y),
+        [0x8, X, Y, 0x3] => Instruction::Xor(X, Y),
+        [0x8, X, Y, 0x4] => Instruction::Add(X, Y),
+        [0x8, X, Y, 0x5] => Instruction::Sub(X, Y),
+        [0x8, X, Y, 0x6] => Instruction::ShiftRight(X, Y),
+        [0x8, X, Y, 0x7] => Instruction::SubReverse(X, Y),
+        [0x8, X, Y, 0xE] => Instruction::ShiftLeft(X, Y),
+        [0x9, X, Y, 0x0] => Instruction::SkipNotEqual(X, Y),
+        [0xA, _, _, _] => Instruction::SetIndex(address),
+        [0xB, _, _, _] => Instruction::JumpOffset(address),
+        [0xC, X, _, _] => Instruction::Random(X, byte_2),
+        [0xD, X, Y, N] => Instruction::Draw(X, Y, N),
+        [0xE, X, 0x9, 0xE] => Instruction::SkipKey(X),
+        [0xE, X, 0xA, 0x1] => Instruction::SkipNotKey(X),
+        [0xF, 0x0, 0x0, 0x0] => Instruction::LongPrefix,
+        [0xF, N, 0x0, 0x1] => Instruction::Planes(N),
+        [0xF, 0x0, 0x0, 0x2] => Instruction::Audio,
+        [0xF, X, 0x0, 0x7] => Instruction::GetDelay(X),
+        [0xF, X, 0x0, 0xA] => Instruction::WaitKey(X),
+        [0xF, X, 0x1, 0x5] => Instruction::SetDelay(X),
+        [0xF, X, 0x1, 0x8] => Instruction::SetSound(X),
+        [0xF, X, 0x1, 0xE] => Instruction::AddIndex(X),
+        [0xF, X, 0x2, 0x9] => Instruction::Font(X),
+        [0xF, X, 0x3, 0x0] => Instruction::BigFont(X),
+        [0xF, X, 0x3, 0x3] => Instruction::Bcd(X),
+        [0xF, X, 0x3, 0xA] => Instruction::Pitch(X),
+        [0xF, X, 0x5, 0x5] => Instruction::Store(X),
+        [0xF, X, 0x6, 0x5] => Instruction::Restore(X),
+        [0xF, X, 0x7, 0x5] => Instruction::SaveFlags(X),
+        [0xF, X, 0x8, 0x5] => Instruction::LoadFlags(X),
+        _ => Instruction::Data(opcode),
+    }
+}
+
+// decodifica en `addr`, incluyendo la dirección de F000 nnnn
+pub fn disassemble_at(memory: &[u8], addr: usize) -> Option<Instruction> {
+    let opcode = read_u16(memory, addr)?;
+
+    match disassemble(opcode) {
+        Instruction::LongPrefix => read_u16(memory, addr + 2).map(Instruction::SetIndexLong),
+        instruction => Some(instruction),
+    }
+}
+
+fn read_u16(memory: &[u8], addr: usize) -> Option<u16> {
+    match memory.get(addr..addr + 2)? {
+        &[high, low] => Some(((high as u16) << 8) | low as u16),
+        _ => None,
+    }
+}
+
+impl Instruction {
+    // tamaño en bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetIndexLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // las de salto condicional saltean la instrucción siguiente
+    pub fn is_skip(&self) -> bool {
+        matches!(self,
+            Instruction::SkipEqualByte(..) |
+            Instruction::SkipNotEqualByte(..) |
+            Instruction::SkipEqual(..) |
+            Instruction::SkipNotEqual(..) |
+            Instruction::SkipKey(_) |
+            Instruction::SkipNotKey(_))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op | (x as u16) << 8 | kk as u16;
+        let x = |op: u16, x: u8| op | (x as u16) << 8;
+
+        let opcode = match *self {
+            Instruction::Nop => 0x0000,
+            Instruction::ScrollDown(n) => 0x00C0 | (n & 0xF) as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | (n & 0xF) as u16,
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(a) => 0x1000 | (a & 0xFFF),
+            Instruction::Call(a) => 0x2000 | (a & 0xFFF),
+            Instruction::SkipEqualByte(vx, kk) => xkk(0x3000, vx, kk),
+            Instruction::SkipNotEqualByte(vx, kk) => xkk(0x4000, vx, kk),
+            Instruction::SkipEqual(vx, vy) => xy(0x5000, vx, vy, 0x0),
+            Instruction::SaveRange(vx, vy) => xy(0x5000, vx, vy, 0x2),
+            Instruction::LoadRange(vx, vy) => xy(0x5000, vx, vy, 0x3),
+            Instruction::SetByte(vx, kk) => xkk(0x6000, vx, kk),
+            Instruction::AddByte(vx, kk) => xkk(0x7000, vx, kk),
+            Instruction::Set(vx, vy) => xy(0x8000, vx, vy, 0x0),
+            Instruction::Or(vx, vy) => xy(0x8000, vx, vy, 0x1),
+            Instruction::And(vx, vy) => xy(0x8000, vx, vy, 0x2),
+            Instruction::Xor(vx, vy) => xy(0x8000, vx, vy, 0x3),
+            Instruction::Add(vx, vy) => xy(0x8000, vx, vy, 0x4),
+            Instruction::Sub(vx, vy) => xy(0x8000, vx, vy, 0x5),
+            Instruction::ShiftRight(vx, vy) => xy(0x8000, vx, vy, 0x6),
+            Instruction::SubReverse(vx, vy) => xy(0x8000, vx, vy, 0x7),
+            Instruction::ShiftLeft(vx, vy) => xy(0x8000, vx, vy, 0xE),
+            Instruction::SkipNotEqual(vx, vy) => xy(0x9000, vx, vy, 0x0),
+            Instruction::SetIndex(a) => 0xA000 | (a & 0xFFF),
+            Instruction::JumpOffset(a) => 0xB000 | (a & 0xFFF),
+            Instruction::Random(vx, kk) => xkk(0xC000, vx, kk),
+            Instruction::Draw(vx, vy, n) => xy(0xD000, vx, vy, (n & 0xF) as u16),
+            Instruction::SkipKey(vx) => x(0xE09E, vx),
+            Instruction::SkipNotKey(vx) => x(0xE0A1, vx),
+            Instruction::LongPrefix => 0xF000,
+            Instruction::SetIndexLong(a) => return vec![0xF0, 0x00, (a >> 8) as u8, a as u8],
+            Instruction::Planes(n) => x(0xF001, n & 0xF),
+            Instruction::Audio => 0xF002,
+            Instruction::GetDelay(vx) => x(0xF007, vx),
+            Instruction::WaitKey(vx) => x(0xF00A, vx),
+            Instruction::SetDelay(vx) => x(0xF015, vx),
+            Instruction::SetSound(vx) => x(0xF018, vx),
+            Instruction::AddIndex(vx) => x(0xF01E, vx),
+            Instruction::Font(vx) => x(0xF029, vx),
+            Instruction::BigFont(vx) => x(0xF030, vx),
+            Instruction::Bcd(vx) => x(0xF033, vx),
+            Instruction::Pitch(vx) => x(0xF03A, vx),
+            Instruction::Store(vx) => x(0xF055, vx),
+            Instruction::Restore(vx) => x(0xF065, vx),
+            Instruction::SaveFlags(vx) => x(0xF075, vx),
+            Instruction::LoadFlags(vx) => x(0xF085, vx),
+            Instruction::Data(word) => word,
+        };
+
+        opcode.to_be_bytes().to_vec()
+    }
+
+    // dirección a la que hace referencia, si tiene una
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump(addr) |
+            Instruction::Call(addr) |
+            Instruction::SetIndex(addr) |
+            Instruction::JumpOffset(addr) |
+            Instruction::SetIndexLong(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    // como Display, pero usando el nombre de la etiqueta para las direcciones
+    pub fn to_string_with(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        let addr = |a: u16| label(a).unwrap_or_else(|| format!("{:#05X}", a));
+        let v = |x: &u8| format!("V{:X}", x);
+        let byte = |b: &u8| format!("{:#04X}", b);
+
+        match self {
+            Instruction::Nop => "NOP".to_string(),
+            Instruction::ScrollDown(n) => format!("SCD {}", n),
+            Instruction::ScrollUp(n) => format!("SCU {}", n),
+            Instruction::Clear => "CLS".to_string(),
+            Instruction::Return => "RET".to_string(),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::LowRes => "LOW".to_string(),
+            Instruction::HighRes => "HIGH".to_string(),
+            Instruction::Jump(a) => format!("JP {}", addr(*a)),
+            Instruction::Call(a) => format!("CALL {}", addr(*a)),
+            Instruction::SkipEqualByte(x, b) => format!("SE {}, {}", v(x), byte(b)),
+            Instruction::SkipNotEqualByte(x, b) => format!("SNE {}, {}", v(x), byte(b)),
+            Instruction::SkipEqual(x, y) => format!("SE {}, {}", v(x), v(y)),
+            Instruction::SaveRange(x, y) => format!("SAVE {}, {}", v(x), v(y)),
+            Instruction::LoadRange(x, y) => format!("LOAD {}, {}", v(x), v(y)),
+            Instruction::SetByte(x, b) => format!("LD {}, {}", v(x), byte(b)),
+            Instruction::AddByte(x, b) => format!("ADD {}, {}", v(x), byte(b)),
+            Instruction::Set(x, y) => format!("LD {}, {}", v(x), v(y)),
+            Instruction::Or(x, y) => format!("OR {}, {}", v(x), v(y)),
+            Instruction::And(x, y) => format!("AND {}, {}", v(x), v(y)),
+            Instruction::Xor(x, y) => format!("XOR {}, {}", v(x), v(y)),
+            Instruction::Add(x, y) => format!("ADD {}, {}", v(x), v(y)),
+            Instruction::Sub(x, y) => format!("SUB {}, {}", v(x), v(y)),
+            Instruction::ShiftRight(x, y) => format!("SHR {}, {}", v(x), v(y)),
+            Instruction::SubReverse(x, y) => format!("SUBN {}, {}", v(x), v(y)),
+            Instruction::ShiftLeft(x, y) => format!("SHL {}, {}", v(x), v(y)),
+            Instruction::SkipNotEqual(x, y) => format!("SNE {}, {}", v(x), v(y)),
+            Instruction::SetIndex(a) => format!("LD I, {}", addr(*a)),
+            Instruction::JumpOffset(a) => format!("JP V0, {}", addr(*a)),
+            Instruction::Random(x, b) => format!("RND {}, {}", v(x), byte(b)),
+            Instruction::Draw(x, y, n) => format!("DRW {}, {}, {}", v(x), v(y), n),
+            Instruction::SkipKey(x) => format!("SKP {}", v(x)),
+            Instruction::SkipNotKey(x) => format!("SKNP {}", v(x)),
+            Instruction::LongPrefix => "LD I, LONG".to_string(),
+            Instruction::SetIndexLong(a) => match label(*a) {
+                Some(label) => format!("LD I, LONG {}", label),
+                None => format!("LD I, LONG {:#06X}", a),
+            },
+            Instruction::Planes(n) => format!("PLANE {}", n),
+            Instruction::Audio => "AUDIO".to_string(),
+            Instruction::GetDelay(x) => format!("LD {}, DT", v(x)),
+            Instruction::WaitKey(x) => format!("LD {}, K", v(x)),
+            Instruction::SetDelay(x) => format!("LD DT, {}", v(x)),
+            Instruction::SetSound(x) => format!("LD ST, {}", v(x)),
+            Instruction::AddIndex(x) => format!("ADD I, {}", v(x)),
+            Instruction::Font(x) => format!("LD F, {}", v(x)),
+            Instruction::BigFont(x) => format!("LD HF, {}", v(x)),
+            Instruction::Bcd(x) => format!("LD B, {}", v(x)),
+            Instruction::Pitch(x) => format!("PITCH {}", v(x)),
+            Instruction::Store(x) => format!("LD [I], {}", v(x)),
+            Instruction::Restore(x) => format!("LD {}, [I]", v(x)),
+            Instruction::SaveFlags(x) => format!("LD R, {}", v(x)),
+            Instruction::LoadFlags(x) => format!("LD {}, R", v(x)),
+            Instruction::Data(word) => format!("DW {:#06X}", word),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with(|_| None))
+    }
+}
+
+// Listado de una ROM cargada en `origin`, en la misma sintaxis que acepta el
+// ensamblador. Se sigue el flujo del programa desde `origin` para separar el
+// código de los datos (sprites, tablas): todo lo que no se alcanza es DB.
+pub fn disassemble_rom(rom: &[u8], origin: u16) -> String {
+    let origin = origin as usize;
+    let code = trace_code(rom, origin);
+
+    // etiquetas: L para saltos y llamadas, D para datos apuntados por I
+    let mut labels = BTreeMap::new();
+    for instruction in code.values() {
+        let Some(target) = instruction.target() else { continue };
+        let target = target as usize;
+        if target < origin || target >= origin + rom.len() {
+            continue;
+        }
+
+        let name = match instruction {
+            Instruction::SetIndex(_) | Instruction::SetIndexLong(_) => format!("D{:03X}", target),
+            _ => format!("L{:03X}", target),
+        };
+        labels.entry(target).or_insert(name);
+    }
+
+    // qué se escribe en cada dirección: una instrucción o una fila de bytes
+    let mut items = Vec::new();
+    let end = origin + rom.len();
+    let mut addr = origin;
+    while addr < end {
+        if let Some(instruction) = code.get(&addr) {
+            items.push((addr, Some(*instruction), instruction.size()));
+            addr += instruction.size();
+            continue;
+        }
+
+        let mut len = 1;
+        while addr + len < end
+            && len < 8
+            && !code.contains_key(&(addr + len))
+            && !labels.contains_key(&(addr + len))
+        {
+            len += 1;
+        }
+        items.push((addr, None, len));
+        addr += len;
+    }
+
+    // las etiquetas que caen en el medio de una instrucción no se pueden usar
+    let starts: BTreeSet<usize> = items.iter().map(|&(addr, _, _)| addr).collect();
+    labels.retain(|addr, _| starts.contains(addr));
+    let label = |a: u16| labels.get(&(a as usize)).cloned();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "; origin {:#05X}, {} bytes", origin, rom.len());
+    for (addr, instruction, len) in items {
+        if let Some(name) = labels.get(&addr) {
+            let _ = writeln!(out, "{}:", name);
+        }
+
+        let bytes = &rom[addr - origin..addr - origin + len];
+        let text = match instruction {
+            Some(instruction) => instruction.to_string_with(label),
+            None => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                format!("DB {}", bytes.join(", "))
+            },
+        };
+        let raw: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let _ = writeln!(out, "    {:<32} ; {:03X}: {}", text, addr, raw.join(" "));
+    }
+
+    out
+}
+
+// recorre el programa desde `origin` siguiendo saltos, llamadas y skips
+fn trace_code(rom: &[u8], origin: usize) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![origin];
+
+    while let Some(mut addr) = pending.pop() {
+        loop {
+            if addr < origin || code.contains_key(&addr) {
+                break;
+            }
+            let Some(instruction) = disassemble_at(rom, addr - origin) else { break };
+            if let Instruction::Data(_) = instruction {
+                break;
+            }
+            code.insert(addr, instruction);
+
+            let next = addr + instruction.size();
+            match instruction {
+                Instruction::Jump(target) => {
+                    pending.push(target as usize);
+                    break;
+                },
+                Instruction::Call(target) => pending.push(target as usize),
+                // no se sabe a dónde van
+                Instruction::Return | Instruction::Exit | Instruction::JumpOffset(_) => break,
+                i if i.is_skip() => {
+                    let skipped = disassemble_at(rom, next - origin).map_or(2, |i| i.size());
+                    pending.push(next + skipped);
+                },
+                _ => {},
+            }
+            addr = next;
+        }
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{assemble, assemble_file};
+    use crate::rng::Rng;
+    use std::path::Path;
+
+    fn round_trip(rom: &[u8], origin: u16) {
+        let listing = disassemble_rom(rom, origin);
+        let assembled = assemble(&listing, origin)
+            .unwrap_or_else(|e| panic!("{}\n{}", e, listing));
+        assert_eq!(assembled, rom, "{}", listing);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(disassemble(0x00e0), Instruction::Clear);
+        assert_eq!(disassemble(0xd12f), Instruction::Draw(1, 2, 0xf));
+        assert_eq!(disassemble(0x5121), Instruction::Data(0x5121));
+        let long = disassemble_at(&[0xf0, 0x00, 0x12, 0x34], 0);
+        assert_eq!(long, Some(Instruction::SetIndexLong(0x1234)));
+        assert_eq!(disassemble_at(&[0xf0, 0x00], 0), None);
+        assert_eq!(Instruction::SetIndex(0x2a0).to_string(), "LD I, 0x2A0");
+    }
+
+    #[test]
+    fn round_trip_test_roms() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "asm") {
+                round_trip(&assemble_file(&path, 0x200).unwrap(), 0x200);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_random_bytes() {
+        let mut rng = Rng::from_state(42);
+        for len in 1..200 {
+            let rom: Vec<u8> = (0..len).map(|_| rng.next_u8()).collect();
+            round_trip(&rom, 0x200);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Sprites de los dígitos hexa para Fx29 (4x5, un byte por fila) y de los
+// dígitos decimales grandes de SUPER-CHIP para Fx30 (8x10).
+
+pub const GLYPHS: usize = 16;
+pub const GLYPH_SIZE: usize = 5;
+pub const BIG_GLYPHS: usize = 10;
+pub const BIG_GLYPH_SIZE: usize = 10;
+pub const FONT_SIZE: usize = GLYPHS * GLYPH_SIZE;
+pub const BIG_FONT_SIZE: usize = BIG_GLYPHS * BIG_GLYPH_SIZE;
+
+const VIP: [u8; FONT_SIZE] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0,
+    0x60, 0x20, 0x20, 0x20, 0x70,
+    0xf0, 0x10, 0xf0, 0x80, 0xf0,
+    0xf0, 0x10, 0xf0, 0x10, 0xf0,
+    0xa0, 0xa0, 0xf0, 0x20, 0x20,
+    0xf0, 0x80, 0xf0, 0x10, 0xf0,
+    0xf0, 0x80, 0xf0, 0x90, 0xf0,
+    0xf0, 0x10, 0x10, 0x10, 0x10,
+    0xf0, 0x90, 0xf0, 0x90, 0xf0,
+    0xf0, 0x90, 0xf0, 0x10, 0xf0,
+    0xf0, 0x90, 0xf0, 0x90, 0x90,
+    0xf0, 0x50, 0x70, 0x50, 0xf0,
+    0xf0, 0x80, 0x80, 0x80, 0xf0,
+    0xf0, 0x50, 0x50, 0x50, 0xf0,
+    0xf0, 0x80, 0xf0, 0x80, 0xf0,
+    0xf0, 0x80, 0xf0, 0x80, 0x80,
+];
+
+const DREAM_6800: [u8; FONT_SIZE] = [
+    0xe0, 0xa0, 0xa0, 0xa0, 0xe0,
+    0x40, 0x40, 0x40, 0x40, 0x40,
+    0xe0, 0x20, 0xe0, 0x80, 0xe0,
+    0xe0, 0x20, 0xe0, 0x20, 0xe0,
+    0x80, 0xa0, 0xa0, 0xe0, 0x20,
+    0xe0, 0x80, 0xe0, 0x20, 0xe0,
+    0xe0, 0x80, 0xe0, 0xa0, 0xe0,
+    0xe0, 0x20, 0x20, 0x20, 0x20,
+    0xe0, 0xa0, 0xe0, 0xa0, 0xe0,
+    0xe0, 0xa0, 0xe0, 0x20, 0xe0,
+    0xe0, 0xa0, 0xe0, 0xa0, 0xa0,
+    0xc0, 0xa0, 0xe0, 0xa0, 0xc0,
+    0xe0, 0x80, 0x80, 0x80, 0xe0,
+    0xc0, 0xa0, 0xa0, 0xa0, 0xc0,
+    0xe0, 0x80, 0xe0, 0x80, 0xe0,
+    0xe0, 0x80, 0xc0, 0x80, 0x80,
+];
+
+const ETI_660: [u8; FONT_SIZE] = [
+    0xe0, 0xa0, 0xa0, 0xa0, 0xe0,
+    0x20, 0x20, 0x20, 0x20, 0x20,
+    0xe0, 0x20, 0xe0, 0x80, 0xe0,
+    0xe0, 0x20, 0xe0, 0x20, 0xe0,
+    0xa0, 0xa0, 0xe0, 0x20, 0x20,
+    0xe0, 0x80, 0xe0, 0x20, 0xe0,
+    0xe0, 0x80, 0xe0, 0xa0, 0xe0,
+    0xe0, 0x20, 0x20, 0x20, 0x20,
+    0xe0, 0xa0, 0xe0, 0xa0, 0xe0,
+    0xe0, 0xa0, 0xe0, 0x20, 0xe0,
+    0xe0, 0xa0, 0xe0, 0xa0, 0xa0,
+    0x80, 0x80, 0xe0, 0xa0, 0xe0,
+    0xe0, 0x80, 0x80, 0x80, 0xe0,
+    0x20, 0x20, 0xe0, 0xa0, 0xe0,
+    0xe0, 0x80, 0xe0, 0x80, 0xe0,
+    0xe0, 0x80, 0xc0, 0x80, 0x80,
+];
+
+const SUPER_CHIP: [u8; FONT_SIZE] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0,
+    0x20, 0x60, 0x20, 0x20, 0x70,
+    0xf0, 0x10, 0xf0, 0x80, 0xf0,
+    0xf0, 0x10, 0xf0, 0x10, 0xf0,
+    0x90, 0x90, 0xf0, 0x10, 0x10,
+    0xf0, 0x80, 0xf0, 0x10, 0xf0,
+    0xf0, 0x80, 0xf0, 0x90, 0xf0,
+    0xf0, 0x10, 0x20, 0x40, 0x40,
+    0xf0, 0x90, 0xf0, 0x90, 0xf0,
+    0xf0, 0x90, 0xf0, 0x10, 0xf0,
+    0xf0, 0x90, 0xf0, 0x90, 0x90,
+    0xe0, 0x90, 0xe0, 0x90, 0xe0,
+    0xf0, 0x80, 0x80, 0x80, 0xf0,
+    0xe0, 0x90, 0x90, 0x90, 0xe0,
+    0xf0, 0x80, 0xf0, 0x80, 0xf0,
+    0xf0, 0x80, 0xf0, 0x80, 0x80,
+];
+
+// SUPER-CHIP 1.1, sólo tiene dígitos
+const SUPER_CHIP_BIG: [u8; BIG_FONT_SIZE] = [
+    0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c,
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c,
+    0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff,
+    0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c,
+    0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06,
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c,
+    0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c,
+    0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c,
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    pub small: [u8; FONT_SIZE],
+    pub big: [u8; BIG_FONT_SIZE],
+}
+
+#[derive(Debug, Error)]
+pub enum FontError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(
+        "a font file has {FONT_SIZE} bytes, or {} with the big digits, not {0}",
+        FONT_SIZE + BIG_FONT_SIZE
+    )]
+    BadSize(usize),
+}
+
+impl Font {
+    // los 16 dígitos chicos y, opcionalmente, los 10 grandes a continuación;
+    // si no están quedan los de SUPER-CHIP
+    pub fn from_bytes(data: &[u8]) -> Result<Font, FontError> {
+        let mut font = FontSet::Vip.font();
+
+        match data.len() {
+            FONT_SIZE => font.small.copy_from_slice(data),
+            len if len == FONT_SIZE + BIG_FONT_SIZE => {
+                font.small.copy_from_slice(&data[..FONT_SIZE]);
+                font.big.copy_from_slice(&data[FONT_SIZE..]);
+            },
+            len => return Err(FontError::BadSize(len)),
+        }
+
+        Ok(font)
+    }
+
+    pub fn load(path: &str) -> Result<Font, FontError> {
+        Font::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        FontSet::Vip.font()
+    }
+}
+
+// Las fuentes de los intérpretes originales
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSet {
+    Vip,
+    Dream6800,
+    Eti660,
+    SuperChip,
+}
+
+impl FontSet {
+    pub const ALL: [FontSet; 4] = [
+        FontSet::Vip,
+        FontSet::Dream6800,
+        FontSet::Eti660,
+        FontSet::SuperChip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FontSet::Vip => "vip",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::Eti660 => "eti660",
+            FontSet::SuperChip => "schip",
+        }
+    }
+
+    pub fn font(self) -> Font {
+        let small = match self {
+            FontSet::Vip => VIP,
+            FontSet::Dream6800 => DREAM_6800,
+            FontSet::Eti660 => ETI_660,
+            FontSet::SuperChip => SUPER_CHIP,
+        };
+
+        Font { small, big: SUPER_CHIP_BIG }
+    }
+}
+
+impl From<FontSet> for Font {
+    fn from(set: FontSet) -> Self {
+        set.font()
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown font '{0}' (expected vip, dream6800, eti660 or schip)")]
+pub struct UnknownFont(String);
+
+impl FromStr for FontSet {
+    type Err = UnknownFont;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Ok(FontSet::Vip),
+            "dream6800" | "dream-6800" | "dream" => Ok(FontSet::Dream6800),
+            "eti660" | "eti-660" | "eti" => Ok(FontSet::Eti660),
+            "schip" | "superchip" | "super-chip" => Ok(FontSet::SuperChip),
+            _ => Err(UnknownFont(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_files() {
+        let small: Vec<u8> = (0..FONT_SIZE as u8).collect();
+        let font = Font::from_bytes(&small).unwrap();
+        assert_eq!(font.small[..], small[..]);
+        assert_eq!(font.big, SUPER_CHIP_BIG);
+
+        let both: Vec<u8> = (0..(FONT_SIZE + BIG_FONT_SIZE) as u8).collect();
+        let font = Font::from_bytes(&both).unwrap();
+        assert_eq!(font.small[..], both[..FONT_SIZE]);
+        assert_eq!(font.big[..], both[FONT_SIZE..]);
+
+        for len in [0, FONT_SIZE - 1, FONT_SIZE + 1, FONT_SIZE + BIG_FONT_SIZE + 1] {
+            let error = Font::from_bytes(&vec![0; len]);
+            assert!(matches!(error, Err(FontError::BadSize(l)) if l == len));
+        }
+    }
+
+    #[test]
+    fn font_set_names() {
+        for set in FontSet::ALL {
+            assert_eq!(set.name().parse::<FontSet>().unwrap(), set);
+        }
+        assert_eq!("COSMAC-VIP".parse::<FontSet>().unwrap(), FontSet::Vip);
+        assert!("amiga".parse::<FontSet>().is_err());
+    }
+}
@@ -1,61 +1,457 @@
-use crate::chip8::Chip8;
+use crate::chip8::{
+    Chip8, StepOutcome, DEFAULT_FONT_ADDRESS, DEFAULT_IPS, DEFAULT_LOAD_ADDRESS, FRAME, PLANES,
+};
+use crate::debugger::{self, Command, Debugger};
+use crate::font::Font;
+use crate::loader::Rom;
+use crate::movie::{Input, Movie};
+use crate::quirks::Quirks;
+use crate::rewind::Rewind;
+use crate::rng::Rng;
 use crate::interface;
 use interface::Action;
+use sdl2::pixels::Color;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 
-pub fn run(file: &str) -> Result<(), anyhow::Error> {
-    let mut game_context = interface::initialize_sdl(8)?;
-    let time_per_frame = Duration::new(1, 0) / 60; // 60 fps
+const STATE_SLOTS: u8 = 10;
+const REWIND_FRAMES: usize = 60 * 30; // 30 segundos
+pub const MIN_IPS: u32 = 60;
+pub const MAX_IPS: u32 = 100_000;
+// 64 pixels de ventana por pixel ya son 4096x2048
+pub const MAX_SCALE: u32 = 64;
+// mientras se mantiene el turbo la emulación va tantas veces más rápido
+const TURBO_FACTOR: u32 = 4;
+
+// configuración del emulador, la arma main con los argumentos
+pub struct Options {
+    // tamaño en la ventana de un pixel de baja resolución
+    pub scale: u32,
+    pub ips: u32,
+    pub quirks: Quirks,
+    pub palette: [Color; 1 << PLANES],
+    pub mute: bool,
+    // entre 0 y 1
+    pub volume: f32,
+    pub fullscreen: bool,
+    pub seed: Option<u64>,
+    pub font: Font,
+    pub font_address: u16,
+    pub load_address: u16,
+    // arrancar con el debugger frenado en la primera instrucción
+    pub debug: bool,
+    pub keymap: Option<String>,
+    // de la base de datos de ROMs, ver interface::add_key_hints
+    pub key_hints: HashMap<String, u8>,
+    // archivo donde grabar las entradas, o de donde reproducirlas
+    pub record: Option<String>,
+    pub replay: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scale: 8,
+            ips: DEFAULT_IPS,
+            quirks: Quirks::default(),
+            palette: interface::DEFAULT_PALETTE,
+            mute: false,
+            volume: 1.0,
+            fullscreen: false,
+            seed: None,
+            font: Font::default(),
+            font_address: DEFAULT_FONT_ADDRESS,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            debug: false,
+            keymap: None,
+            key_hints: HashMap::new(),
+            record: None,
+            replay: None,
+        }
+    }
+}
+
+// Mientras se graba o se reproduce una película la emulación avanza de a
+// frames fijos en vez de seguir al reloj, para que sea determinista. Volver
+// atrás o cargar un estado la desincronizaría, así que no se puede.
+enum MovieMode {
+    Off,
+    Recording { movie: Movie, path: String },
+    // next: el próximo evento a aplicar
+    Replaying { movie: Movie, next: usize },
+}
+
+impl MovieMode {
+    fn is_active(&self) -> bool {
+        !matches!(self, MovieMode::Off)
+    }
+
+    fn is_replaying(&self) -> bool {
+        matches!(self, MovieMode::Replaying { .. })
+    }
+
+    fn record(&mut self, frame: u64, input: Input) {
+        if let MovieMode::Recording { movie, .. } = self {
+            movie.record(frame, input);
+        }
+    }
+}
+
+fn apply_input(chip8: &mut Chip8, input: Input) {
+    match input {
+        Input::Press(key) => chip8.key_press(key),
+        Input::Release(key) => chip8.key_release(key),
+        Input::Speed(ips) => chip8.set_instructions_per_second(ips),
+    }
+}
+
+fn state_path(file: &str, slot: u8) -> String {
+    format!("{}.state{}", file, slot)
+}
+
+// lee stdin en otro thread para que la ventana siga respondiendo mientras
+// el debugger espera comandos
+fn spawn_stdin_reader() -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+// +/- cambian la velocidad un 25%
+fn speed_up(ips: u32) -> u32 {
+    (ips + ips / 4).clamp(MIN_IPS, MAX_IPS)
+}
 
+fn slow_down(ips: u32) -> u32 {
+    (ips - ips / 5).clamp(MIN_IPS, MAX_IPS)
+}
+
+fn title(file: &str, ips: u32, turbo: bool) -> String {
+    if turbo {
+        format!("CHIP-8 - {} - {} IPS (x{})", file, ips, TURBO_FACTOR)
+    } else {
+        format!("CHIP-8 - {} - {} IPS", file, ips)
+    }
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    let _ = std::io::stdout().flush();
+}
+
+// para un zip con varias ROMs, antes de arrancar; None si no se eligió
+pub fn pick_rom(
+    file: &str,
+    roms: &[String],
+    options: &Options
+) -> Result<Option<String>, anyhow::Error> {
+    let mut game_context = interface::initialize_sdl(options.scale, options.fullscreen)?;
+    game_context.palette = options.palette;
+    interface::set_title(&mut game_context, &format!("CHIP-8 - {} - choose a ROM", file));
+
+    let picked = interface::pick(&mut game_context, roms)?;
+    Ok(picked.map(|i| roms[i].clone()))
+}
 
-    let mut chip8 = Chip8::new();
+pub fn run(rom: &Rom, options: &Options) -> Result<(), anyhow::Error> {
+    let file = rom.name.as_str();
+    let mut game_context = interface::initialize_sdl(options.scale, options.fullscreen)?;
+    game_context.palette = options.palette;
+    interface::set_volume(&mut game_context.sound_device, options.volume);
+
+
+    // al reproducir, la configuración es la de la película
+    let replay = options.replay.as_deref().map(Movie::load).transpose()?;
+    let (seed, quirks, ips) = match &replay {
+        Some(movie) => (movie.seed, movie.quirks, movie.ips),
+        None => (options.seed.unwrap_or_else(rand::random), options.quirks, options.ips),
+    };
+    let (font, font_address, load_address) = match &replay {
+        Some(movie) => (movie.font.clone(), movie.font_address, movie.load_address),
+        None => (options.font.clone(), options.font_address, options.load_address),
+    };
+    // siempre con semilla conocida, para poder repetir la ejecución
+    eprintln!("Seed: {}", seed);
+
+    let mut chip8 = Chip8::with_rng(quirks, Rng::from_state(seed));
+    chip8.set_font(&font, font_address)?;
+    chip8.set_load_address(load_address);
     // Cargar ROM
-    chip8.load_rom(file)?;
+    chip8.load_rom_bytes(&rom.data)?;
+    // main ya las valida, pero una película puede traer cualquier cosa
+    if !(MIN_IPS..=MAX_IPS).contains(&ips) {
+        eprintln!("{} IPS is out of range, using {}", ips, ips.clamp(MIN_IPS, MAX_IPS));
+    }
+    chip8.set_instructions_per_second(ips.clamp(MIN_IPS, MAX_IPS));
+    let rom_hash = chip8.rom_hash().unwrap_or_default().to_string();
+    let mut keymap = interface::default_keymap();
+    interface::add_key_hints(&mut keymap, &options.key_hints);
+    if let Some(path) = &options.keymap {
+        keymap = interface::load_keymap(path, Some(&rom_hash), keymap)?;
+    }
+    game_context.keymap = keymap;
+
+    let mut movie = match (replay, &options.record) {
+        (Some(movie), _) => {
+            if movie.rom_hash != rom_hash {
+                anyhow::bail!("the movie was recorded with a different ROM ({})", movie.rom_hash);
+            }
+            println!("Replaying {} frames", movie.len());
+            MovieMode::Replaying { movie, next: 0 }
+        },
+        (None, Some(path)) => {
+            let mut movie = Movie::new(&rom_hash, seed, quirks, chip8.instructions_per_second());
+            movie.font = font;
+            movie.font_address = font_address;
+            movie.load_address = load_address;
+            MovieMode::Recording { movie, path: path.clone() }
+        },
+        (None, None) => MovieMode::Off,
+    };
+    // frames emulados desde que arrancó, para la película
+    let mut frame: u64 = 0;
+    // si algo falla lo grabado se guarda igual
+    let result = play(&mut game_context, &mut chip8, &mut movie, &mut frame, options, file);
+
+    if let MovieMode::Recording { mut movie, path } = movie {
+        movie.finish(frame);
+        movie.save(&path)?;
+        println!("Saved movie to {}", path);
+    }
+
+    result
+}
+
+fn play(
+    game_context: &mut interface::SdlContext,
+    chip8: &mut Chip8,
+    movie: &mut MovieMode,
+    frame: &mut u64,
+    options: &Options,
+    file: &str
+) -> Result<(), anyhow::Error> {
+    let time_per_frame = Duration::new(1, 0) / 60; // 60 fps
+    let mut turbo = false;
+    // lo que muestra el título de la ventana
+    let mut shown_speed = (0, false);
+
+    let mut slot = 0;
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut rewinding = false;
+    let mut debugger = Debugger::new();
+    if options.debug {
+        debugger.pause();
+    }
+    let mut was_paused = false;
+    let commands = spawn_stdin_reader();
+    let mut last_frame = Instant::now();
 
     'game: loop {
         let now = Instant::now();
+        // lo que pasó mientras estaba pausado o rebobinando se descarta
+        let elapsed = now - last_frame;
+        last_frame = now;
 
         //  manejar eventos
-        match interface::check_input(&mut game_context.event_pump) {
-            Some(Action::Quit) => break 'game,
-            Some(Action::Press(key)) => {
-                println!("Pressed: {}", key);
-                chip8.key_press(key);
-            },
-            Some(Action::Release(key)) => {
-                println!("Released: {}", key);
+        for action in interface::check_input(game_context) {
+            match action {
+                Action::Quit => break 'game,
+                // mientras se reproduce una película el teclado no hace nada
+                Action::Press(_) | Action::Release(_) if movie.is_replaying() => {},
+                Action::Press(key) => {
+                    println!("Pressed: {}", key);
+                    chip8.key_press(key);
+                    movie.record(*frame, Input::Press(key));
+                },
+                Action::Release(key) => {
+                    println!("Released: {}", key);
+
+                    chip8.key_release(key);
+                    movie.record(*frame, Input::Release(key));
+                },
+                // cambian el estado o (el debugger) cortan frames: la película se desincroniza
+                Action::LoadState | Action::RewindStart | Action::TogglePause
+                    if movie.is_active() =>
+                {
+                    println!("Not available while recording or replaying a movie");
+                },
+                Action::SaveState => {
+                    match std::fs::write(state_path(file, slot), chip8.save_state()) {
+                        Ok(()) => println!("Saved state to slot {}", slot),
+                        Err(e) => eprintln!("Couldn't save state: {}", e),
+                    }
+                },
+                Action::LoadState => {
+                    let loaded = std::fs::read(state_path(file, slot))
+                        .map_err(anyhow::Error::from)
+                        .and_then(|data| Ok(chip8.load_state_keeping_keys(&data)?));
 
-                chip8.key_release(key);
-            },
-            None => {},
+                    match loaded {
+                        Ok(()) => println!("Loaded state from slot {}", slot),
+                        Err(e) => eprintln!("Couldn't load state: {}", e),
+                    }
+                },
+                Action::PreviousSlot => {
+                    slot = (slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                    println!("Slot {}", slot);
+                },
+                Action::NextSlot => {
+                    slot = (slot + 1) % STATE_SLOTS;
+                    println!("Slot {}", slot);
+                },
+                Action::RewindStart => rewinding = true,
+                Action::RewindStop => rewinding = false,
+                Action::SpeedUp | Action::SlowDown if movie.is_replaying() => {},
+                Action::SpeedUp => {
+                    let ips = speed_up(chip8.instructions_per_second());
+                    chip8.set_instructions_per_second(ips);
+                    movie.record(*frame, Input::Speed(ips));
+                },
+                Action::SlowDown => {
+                    let ips = slow_down(chip8.instructions_per_second());
+                    chip8.set_instructions_per_second(ips);
+                    movie.record(*frame, Input::Speed(ips));
+                },
+                Action::TurboStart => turbo = true,
+                Action::TurboStop => turbo = false,
+                Action::TogglePause => {
+                    if debugger.is_paused() {
+                        debugger.resume(chip8);
+                        println!();
+                    } else {
+                        debugger.pause();
+                    }
+                },
+            }
         }
 
-        // decrease timers
-        chip8.decrease_delay_timer();
-        chip8.decrease_sound_timer();
+        if (chip8.instructions_per_second(), turbo) != shown_speed {
+            shown_speed = (chip8.instructions_per_second(), turbo);
+            interface::set_title(game_context, &title(file, shown_speed.0, turbo));
+        }
+
+        // debugger: al frenar se muestran los registros y se esperan comandos
+        if debugger.is_paused() {
+            if !was_paused {
+                if let Some(reason) = debugger.reason() {
+                    println!("{}", reason);
+                }
+                println!("{}", debugger::registers(chip8));
+                prompt();
+                was_paused = true;
+            }
+
+            interface::stop_sound(&game_context.sound_device);
+
+            while let Ok(line) = commands.try_recv() {
+                match line.parse::<Command>() {
+                    Ok(Command::Quit) => break 'game,
+                    Ok(command) => {
+                        let out = debugger.execute(command, chip8);
+                        if !out.is_empty() {
+                            println!("{}", out);
+                        }
+                    },
+                    Err(debugger::CommandError::Empty) => {},
+                    Err(e) => println!("{}", e),
+                }
+
+                if !debugger.is_paused() {
+                    break;
+                }
+                prompt();
+            }
+
+            if debugger.is_paused() {
+                render(game_context, chip8);
+                std::thread::sleep(time_per_frame.saturating_sub(now.elapsed()));
+                continue 'game;
+            }
+        }
+        was_paused = false;
+
+        // mientras se mantiene apretado, un frame para atrás por iteración
+        if rewinding {
+            rewind.rewind(chip8)?;
+            interface::stop_sound(&game_context.sound_device);
+            render(game_context, chip8);
+            std::thread::sleep(time_per_frame.saturating_sub(now.elapsed()));
+            continue 'game;
+        }
+
+        // la instrucción en la que frena cuenta para los timers y el vblank
+        let mut step = |chip8: &mut Chip8| debugger.step(chip8);
+
+        if movie.is_active() {
+            //  con película, frames fijos (varios por iteración en turbo)
+            for _ in 0..if turbo { TURBO_FACTOR } else { 1 } {
+                if let MovieMode::Replaying { movie: replay, next } = movie {
+                    while let Some(event) = replay.events().get(*next).filter(|e| e.frame <= *frame) {
+                        apply_input(chip8, event.input);
+                        *next += 1;
+                    }
+
+                    if *frame >= replay.len() {
+                        println!("Replay finished");
+                        *movie = MovieMode::Off;
+                    }
+                }
+
+                let outcome = chip8.run_for_with(FRAME, &mut step)?;
+                *frame += 1;
+                match outcome {
+                    Some(StepOutcome::Exited) => break 'game,
+                    None => break,
+                    Some(_) => {},
+                }
+            }
+        } else {
+            //  avanzar emulación lo que pasó desde el frame anterior, los
+            //  timers los baja el scheduler a 60 Hz
+            let elapsed = if turbo { elapsed * TURBO_FACTOR } else { elapsed };
+            let outcome = chip8.run_for_with(elapsed, &mut step)?;
+            if outcome == Some(StepOutcome::Exited) {
+                break 'game;
+            }
+        }
 
         // play sounds
-        if chip8.sound_timer() > 0 {
+        if chip8.sound_timer() > 0 && !options.mute {
+            interface::update_sound(
+                &mut game_context.sound_device,
+                chip8.audio_pattern(),
+                chip8.playback_rate()
+            );
             interface::play_sound(&game_context.sound_device);
         } else {
             interface::stop_sound(&game_context.sound_device);
         }
 
 
-        //  avanzar emulación (correr 8 veces por iteración)
-        for _ in 0..8{
-            chip8.step();
-        }
+        rewind.record(chip8);
 
         //  dibujar pantalla
-        if let Err(e) = interface::render_window(
-            &mut game_context,
-            &chip8.screen
-        ) {
-            eprintln!("{}", e);
-        }
+        render(game_context, chip8);
         std::thread::sleep(time_per_frame.saturating_sub(now.elapsed()));
     }
 
     Ok(())
 }
+
+fn render(game_context: &mut interface::SdlContext, chip8: &Chip8) {
+    if let Err(e) = interface::render_window(game_context, &chip8.screen) {
+        eprintln!("{}", e);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::chip8::{Chip8, Chip8Error, Screen, StepOutcome, DEFAULT_PALETTE, FRAME};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Correr el core sin ventana ni sonido, para tests y CI.
+
+// una tecla sin + ni - en el script se suelta después de estos frames
+const TAP_FRAMES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid key event '{0}' (expected FRAME:+KEY, FRAME:-KEY or FRAME:KEY)")]
+pub struct KeyScriptError(String);
+
+// Eventos separados por comas o espacios, con la tecla en hexa:
+//
+//     10:+5     apretar 5 en el frame 10
+//     40:-5     soltarla en el 40
+//     60:A      apretar y soltar A unos frames después
+pub fn parse_keys(script: &str) -> Result<Vec<KeyEvent>, KeyScriptError> {
+    let mut events = Vec::new();
+
+    for event in script.split(|c: char| c == ',' || c.is_whitespace()) {
+        if event.is_empty() {
+            continue;
+        }
+
+        let err = || KeyScriptError(event.to_string());
+        let (frame, key) = event.split_once(':').ok_or_else(err)?;
+        let frame: u32 = frame.parse().map_err(|_| err())?;
+
+        let (press, release, key) = match key.as_bytes().first() {
+            Some(b'+') => (true, false, &key[1..]),
+            Some(b'-') => (false, true, &key[1..]),
+            _ => (true, true, key),
+        };
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|&k| k < 16)
+            .ok_or_else(err)?;
+
+        if press {
+            events.push(KeyEvent { frame, key, pressed: true });
+        }
+        if release {
+            let frame = if press { frame.saturating_add(TAP_FRAMES) } else { frame };
+            events.push(KeyEvent { frame, key, pressed: false });
+        }
+    }
+
+    // estable: dos eventos del mismo frame quedan en el orden del script
+    events.sort_by_key(|e| e.frame);
+
+    Ok(events)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // se corrieron todos los frames pedidos
+    Frames,
+    // 00FD
+    Exited,
+    // un salto a sí mismo, no va a cambiar nada más
+    Loop(u16),
+    // Fx0A y no quedan eventos de teclado en el script
+    WaitingForKey(u16),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Frames => write!(f, "frame limit reached"),
+            Stop::Exited => write!(f, "program exited"),
+            Stop::Loop(addr) => write!(f, "infinite loop at {:#05X}", addr),
+            Stop::WaitingForKey(addr) => write!(f, "waiting for a key at {:#05X}", addr),
+        }
+    }
+}
+
+// Corre hasta `frames` frames de 60 Hz aplicando los eventos de teclado al
+// principio de su frame. Devuelve cuántos frames se corrieron y por qué paró.
+pub fn run(chip8: &mut Chip8, frames: u32, keys: &[KeyEvent]) -> Result<(u32, Stop), Chip8Error> {
+    let mut keys = keys.iter().peekable();
+
+    for frame in 0..frames {
+        while let Some(event) = keys.next_if(|e| e.frame <= frame) {
+            if event.pressed {
+                chip8.key_press(event.key);
+            } else {
+                chip8.key_release(event.key);
+            }
+        }
+
+        let outcome = chip8.run_for(FRAME)?;
+        if outcome == Some(StepOutcome::Exited) {
+            return Ok((frame + 1, Stop::Exited));
+        }
+
+        // los eventos que faltan no importan, un 1nnn a sí mismo no lee
+        // el teclado
+        let pc = chip8.program_counter();
+        if jumps_to_itself(chip8, pc)? {
+            return Ok((frame + 1, Stop::Loop(pc)));
+        }
+        // ya vio todos los eventos y sigue esperando
+        if outcome == Some(StepOutcome::WaitingForKey) && keys.peek().is_none() {
+            return Ok((frame + 1, Stop::WaitingForKey(pc)));
+        }
+    }
+
+    Ok((frames, Stop::Frames))
+}
+
+// 1nnn sólo llega a los primeros 4K, más arriba no hay salto a sí mismo
+fn jumps_to_itself(chip8: &Chip8, pc: u16) -> Result<bool, Chip8Error> {
+    Ok(pc <= 0xFFF && chip8.opcode_at(pc)? == 0x1000 | pc)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ascii,
+    Pbm,
+    Png,
+    Hash,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown format '{0}' (expected ascii, pbm, png or hash)")]
+pub struct UnknownFormat(String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ascii" | "txt" => Ok(Format::Ascii),
+            "pbm" => Ok(Format::Pbm),
+            "png" => Ok(Format::Png),
+            "hash" => Ok(Format::Hash),
+            _ => Err(UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+pub fn dump(screen: &Screen, format: Format) -> Vec<u8> {
+    match format {
+        Format::Ascii => ascii(screen).into_bytes(),
+        Format::Pbm => pbm(screen).into_bytes(),
+        Format::Png => png(screen),
+        Format::Hash => format!("{:016x}\n", hash(screen)).into_bytes(),
+    }
+}
+
+// '.' apagado, '#' el color 1 y el número de color para los demás (XO-CHIP)
+pub fn ascii(screen: &Screen) -> String {
+    let mut out = String::with_capacity((screen.width() + 1) * screen.height());
+
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            out.push(match screen.get_color((x, y)) {
+                0 => '.',
+                1 => '#',
+                color => char::from_digit(color as u32, 16).unwrap_or('?'),
+            });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+// PBM de texto (P1), cualquier color distinto de 0 es un 1
+pub fn pbm(screen: &Screen) -> String {
+    let mut out = format!("P1\n{} {}\n", screen.width(), screen.height());
+
+    for y in 0..screen.height() {
+        let row: Vec<&str> = (0..screen.width())
+            .map(|x| if screen.get_pixel((x, y)) { "1" } else { "0" })
+            .collect();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+
+    out
+}
+
+// FNV-1a de 64 bits sobre el tamaño y los colores, para comparar pantallas
+pub fn hash(screen: &Screen) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    let mut feed = |byte: u8| {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    };
+
+    feed(screen.width() as u8);
+    feed(screen.height() as u8);
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            feed(screen.get_color((x, y)));
+        }
+    }
+
+    hash
+}
+
+// PNG con paleta, un pixel por pixel del CHIP-8. Los datos van sin
+// comprimir (bloques "stored" de deflate), para una pantalla de a lo sumo
+// 128x64 no vale la pena.
+pub fn png(screen: &Screen) -> Vec<u8> {
+    let (width, height) = (screen.width(), screen.height());
+
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        raw.push(0); // filtro: ninguno
+        raw.extend((0..width).map(|x| screen.get_color((x, y))));
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits por pixel, color indexado, sin entrelazado
+    ihdr.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut out, b"IHDR", &ihdr);
+    png_chunk(&mut out, b"PLTE", DEFAULT_PALETTE.concat().as_slice());
+    png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut out, b"IEND", &[]);
+
+    out
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+
+    let crc = crc32(kind.iter().chain(data));
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+fn crc32<'a>(data: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn key_script() {
+        let events = parse_keys("10:+5, 40:-5 2:a").unwrap();
+        let event = |frame, key, pressed| KeyEvent { frame, key, pressed };
+        assert_eq!(events, [
+            event(2, 0xA, true),
+            event(2 + TAP_FRAMES, 0xA, false),
+            event(10, 5, true),
+            event(40, 5, false),
+        ]);
+        assert_eq!(parse_keys(&format!("{}:1", u32::MAX)).unwrap()[1].frame, u32::MAX);
+
+        for bad in ["5", "x:1", "1:10", "1:+", "-1:2"] {
+            assert!(parse_keys(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn loops_only_in_the_first_4k() {
+        let mut c = Chip8::new();
+        c.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert!(jumps_to_itself(&c, 0x200).unwrap());
+
+        // 1000 salta a 0x000, no a 0x1000
+        let mut c = Chip8::with_quirks(Quirks::XO_CHIP);
+        c.set_load_address(0x1000);
+        c.load_rom_bytes(&[0x10, 0x00]).unwrap();
+        assert!(!jumps_to_itself(&c, 0x1000).unwrap());
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789".iter()), 0xCBF4_3926);
+        assert_eq!(crc32(b"".iter()), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn png_layout() {
+        let png = png(&Chip8::new().screen);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+    }
+}
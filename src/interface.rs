@@ -2,24 +2,45 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use anyhow::anyhow;
 use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use crate::chip8;
-use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
-use std::collections::HashMap;
+use crate::chip8::{AUDIO_PATTERN_SIZE, PLANES, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::chip8::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH};
+use std::collections::{HashMap, HashSet};
 
-pub struct SquareWave {
+// frecuencia del beeper cuando la ROM no cargó un patrón de audio
+const BEEP_FREQUENCY: f32 = 800.0;
+// amplitud con el volumen al 100%
+const MAX_VOLUME: f32 = 0.25;
+// un stick cuenta como apretado pasada la mitad del recorrido
+const AXIS_THRESHOLD: i16 = i16::MAX / 2;
+
+pub struct PatternWave {
+    // None: onda cuadrada como el beeper original
+    pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    // fase en [0, 1), una vuelta es un período de la onda o del patrón
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    freq: f32,
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for PatternWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
+            let on = match &self.pattern {
+                None => self.phase <= 0.5,
+                Some(pattern) => {
+                    let bit = (self.phase * (AUDIO_PATTERN_SIZE * 8) as f32) as usize;
+                    (pattern[bit / 8] >> (7 - bit % 8)) & 0x1 == 1
+                },
+            };
+
+            *x = if on {
                 self.volume
             } else {
                 -self.volume
@@ -30,11 +51,326 @@ impl AudioCallback for SquareWave {
     }
 }
 
+pub const DEFAULT_PALETTE: [Color; 1 << PLANES] = {
+    let mut palette = [Color::RGB(0, 0, 0); 1 << PLANES];
+    let mut i = 0;
+    while i < palette.len() {
+        let [r, g, b] = chip8::DEFAULT_PALETTE[i];
+        palette[i] = Color::RGB(r, g, b);
+        i += 1;
+    }
+    palette
+};
+
+const GREEN_PALETTE: [(u8, u8, u8); 4] = [
+    (0x0F, 0x38, 0x0F),
+    (0x9B, 0xBC, 0x0F),
+    (0x8B, 0xAC, 0x0F),
+    (0x30, 0x62, 0x30),
+];
+
+const AMBER_PALETTE: [(u8, u8, u8); 4] = [
+    (0x1A, 0x0F, 0x00),
+    (0xFF, 0xB0, 0x00),
+    (0xCC, 0x88, 0x00),
+    (0x66, 0x44, 0x00),
+];
+
+// un nombre (default, green, amber) o una lista de colores RRGGBB separados
+// por comas; los colores que no se dan quedan como en la paleta por defecto
+pub fn parse_palette(s: &str) -> Result<[Color; 1 << PLANES], anyhow::Error> {
+    let mut palette = DEFAULT_PALETTE;
+
+    let preset = match s.to_ascii_lowercase().as_str() {
+        "default" => return Ok(palette),
+        "green" => Some(GREEN_PALETTE),
+        "amber" => Some(AMBER_PALETTE),
+        _ => None,
+    };
+    if let Some(preset) = preset {
+        for (color, &(r, g, b)) in palette.iter_mut().zip(preset.iter()) {
+            *color = Color::RGB(r, g, b);
+        }
+        return Ok(palette);
+    }
+
+    let colors: Vec<&str> = s.split(',').map(str::trim).collect();
+    if colors.len() > palette.len() {
+        return Err(anyhow!("a palette has at most {} colors", palette.len()));
+    }
+
+    for (color, hex) in palette.iter_mut().zip(colors) {
+        let hex = hex.trim_start_matches('#');
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or_else(|| anyhow!("invalid color '{}' (expected RRGGBB)", hex))?;
+
+        *color = Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+    }
+
+    Ok(palette)
+}
+
+// botón del joystick, o un eje pasado el umbral hacia un lado
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PadInput {
+    Button(Button),
+    AxisPlus(Axis),
+    AxisMinus(Axis),
+}
+
+impl PadInput {
+    // con los nombres de los mappings de SDL: "a", "dpup", "+leftx", "-lefty"
+    pub fn from_name(name: &str) -> Option<PadInput> {
+        match name.as_bytes().first() {
+            Some(b'+') => Axis::from_string(&name[1..]).map(PadInput::AxisPlus),
+            Some(b'-') => Axis::from_string(&name[1..]).map(PadInput::AxisMinus),
+            _ => Button::from_string(name).map(PadInput::Button),
+        }
+    }
+}
+
+// a qué tecla del CHIP-8 va cada tecla del teclado y cada botón
+pub struct Keymap {
+    pub keys: HashMap<Keycode, u8>,
+    pub pad: HashMap<PadInput, u8>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        default_keymap()
+    }
+}
+
+// en el joystick las direcciones son W A S D, A es E y B es Q
+pub fn default_keymap() -> Keymap {
+    let keys = HashMap::from([
+        (Keycode::Num1, 0x1),
+        (Keycode::Num2, 0x2),
+        (Keycode::Num3, 0x3),
+        (Keycode::Num4, 0xC),
+        (Keycode::Q, 0x4),
+        (Keycode::W, 0x5),
+        (Keycode::E, 0x6),
+        (Keycode::R, 0xD),
+        (Keycode::A, 0x7),
+        (Keycode::S, 0x8),
+        (Keycode::D, 0x9),
+        (Keycode::F, 0xE),
+        (Keycode::Z, 0xA),
+        (Keycode::X, 0x0),
+        (Keycode::C, 0xB),
+        (Keycode::V, 0xF)
+    ]);
+    let pad = HashMap::from([
+        (PadInput::Button(Button::DPadUp), 0x5),
+        (PadInput::Button(Button::DPadLeft), 0x7),
+        (PadInput::Button(Button::DPadDown), 0x8),
+        (PadInput::Button(Button::DPadRight), 0x9),
+        (PadInput::AxisMinus(Axis::LeftY), 0x5),
+        (PadInput::AxisMinus(Axis::LeftX), 0x7),
+        (PadInput::AxisPlus(Axis::LeftY), 0x8),
+        (PadInput::AxisPlus(Axis::LeftX), 0x9),
+        (PadInput::Button(Button::A), 0x6),
+        (PadInput::Button(Button::B), 0x4),
+    ]);
+
+    Keymap { keys, pad }
+}
+
+// Las teclas que sugiere la base de datos de ROMs ("up", "a", ...): las
+// direcciones van a las flechas, al pad y al stick, A y B sólo al joystick
+pub fn add_key_hints(keymap: &mut Keymap, hints: &HashMap<String, u8>) {
+    for (name, &key) in hints.iter().filter(|(_, &k)| k < 16) {
+        let (keycode, pad): (Option<Keycode>, &[PadInput]) = match name.as_str() {
+            "up" => (
+                Some(Keycode::Up),
+                &[PadInput::Button(Button::DPadUp), PadInput::AxisMinus(Axis::LeftY)],
+            ),
+            "down" => (
+                Some(Keycode::Down),
+                &[PadInput::Button(Button::DPadDown), PadInput::AxisPlus(Axis::LeftY)],
+            ),
+            "left" => (
+                Some(Keycode::Left),
+                &[PadInput::Button(Button::DPadLeft), PadInput::AxisMinus(Axis::LeftX)],
+            ),
+            "right" => (
+                Some(Keycode::Right),
+                &[PadInput::Button(Button::DPadRight), PadInput::AxisPlus(Axis::LeftX)],
+            ),
+            "a" => (None, &[PadInput::Button(Button::A)]),
+            "b" => (None, &[PadInput::Button(Button::B)]),
+            _ => continue,
+        };
+
+        keymap.keys.extend(keycode.map(|k| (k, key)));
+        keymap.pad.extend(pad.iter().map(|&p| (p, key)));
+    }
+}
+
+// Estilo INI, una línea por tecla del CHIP-8, en hexa, con las teclas de
+// SDL que la activan. Los botones del joystick van con "pad:" y el nombre
+// que usa SDL en los mappings ("pad:a", "pad:dpup", "pad:-lefty" para el
+// stick hacia arriba). Las secciones con el SHA-1 de una ROM cambian el
+// mapa sólo para esa ROM:
+//
+//     # comentario
+//     5 = W, Up, pad:dpup
+//     8 = S, Down, pad:dpdown
+//
+//     [2fd4e1c67a2d28fced849ee1bb76e7391b93eb12]
+//     5 = Space, pad:a
+//
+// las teclas que no aparecen quedan como en `keymap`
+pub fn load_keymap(
+    path: &str,
+    rom_hash: Option<&str>,
+    mut keymap: Keymap
+) -> Result<Keymap, anyhow::Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("{}: {}", path, e))?;
+    // antes de la primera sección vale para todas las ROMs
+    let mut applies = true;
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let err = |msg: String| anyhow!("{}:{}: {}", path, n + 1, msg);
+
+        if let Some(section) = line.strip_prefix('[') {
+            let hash = section
+                .strip_suffix(']')
+                .map(str::trim)
+                .filter(|h| h.len() == 40 && h.chars().all(|c| c.is_ascii_hexdigit()))
+                .ok_or_else(|| err("expected '[<ROM SHA-1>]'".to_string()))?;
+            applies = rom_hash.is_some_and(|rom| rom.eq_ignore_ascii_case(hash));
+            continue;
+        }
+
+        let (key, host_keys) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected '<key> = <host keys>'".to_string()))?;
+        let key = u8::from_str_radix(key.trim(), 16)
+            .ok()
+            .filter(|&k| k < 16)
+            .ok_or_else(|| err(format!("invalid CHIP-8 key '{}'", key.trim())))?;
+
+        // las secciones de otras ROMs se validan igual
+        let mut keycodes = Vec::new();
+        let mut pad = Vec::new();
+        for name in host_keys.split(',').map(str::trim) {
+            match name.strip_prefix("pad:") {
+                Some(button) => pad.push(
+                    PadInput::from_name(button)
+                        .ok_or_else(|| err(format!("unknown controller input '{}'", button)))?
+                ),
+                None => keycodes.push(
+                    Keycode::from_name(name)
+                        .ok_or_else(|| err(format!("unknown key '{}'", name)))?
+                ),
+            }
+        }
+
+        // sólo se reemplaza lo del mismo tipo: "5 = W" deja el joystick como estaba
+        if applies {
+            if !keycodes.is_empty() {
+                keymap.keys.retain(|_, k| *k != key);
+            }
+            if !pad.is_empty() {
+                keymap.pad.retain(|_, k| *k != key);
+            }
+            keymap.keys.extend(keycodes.into_iter().map(|k| (k, key)));
+            keymap.pad.extend(pad.into_iter().map(|p| (p, key)));
+        }
+    }
+
+    Ok(keymap)
+}
+
+// Los joysticks abiertos, se conectan y desconectan en cualquier momento
+pub struct Pads {
+    subsystem: sdl2::GameControllerSubsystem,
+    open: HashMap<u32, GameController>,
+    // (id del joystick, entrada) apretadas ahora
+    held: HashSet<(u32, PadInput)>,
+}
+
+impl Pads {
+    fn new(subsystem: sdl2::GameControllerSubsystem) -> Pads {
+        Pads { subsystem, open: HashMap::new(), held: HashSet::new() }
+    }
+
+    // SDL avisa también de los que ya estaban conectados al arrancar
+    fn added(&mut self, index: u32) {
+        match self.subsystem.open(index) {
+            Ok(pad) => {
+                println!("Controller connected: {}", pad.name());
+                self.open.insert(pad.instance_id(), pad);
+            },
+            Err(e) => eprintln!("Couldn't open controller {}: {}", index, e),
+        }
+    }
+
+    // lo que tenía apretado se suelta
+    fn removed(&mut self, id: u32) -> Vec<(PadInput, bool)> {
+        if let Some(pad) = self.open.remove(&id) {
+            println!("Controller disconnected: {}", pad.name());
+        }
+
+        let released = self.held
+            .iter()
+            .filter(|(pad, _)| *pad == id)
+            .map(|&(_, input)| (input, false))
+            .collect();
+        self.held.retain(|(pad, _)| *pad != id);
+
+        released
+    }
+
+    // Some si cambió
+    fn set(&mut self, id: u32, input: PadInput, down: bool) -> Option<(PadInput, bool)> {
+        let changed = match down {
+            true => self.held.insert((id, input)),
+            false => self.held.remove(&(id, input)),
+        };
+        changed.then_some((input, down))
+    }
+
+    fn button(&mut self, id: u32, button: Button, down: bool) -> Vec<(PadInput, bool)> {
+        self.set(id, PadInput::Button(button), down).into_iter().collect()
+    }
+
+    fn axis(&mut self, id: u32, axis: Axis, value: i16) -> Vec<(PadInput, bool)> {
+        [
+            (PadInput::AxisPlus(axis), value > AXIS_THRESHOLD),
+            (PadInput::AxisMinus(axis), value < -AXIS_THRESHOLD),
+        ]
+            .into_iter()
+            .filter_map(|(input, down)| self.set(id, input, down))
+            .collect()
+    }
+}
 
 pub enum Action {
     Quit,
     Press(u8),
-    Release(u8)
+    Release(u8),
+    SaveState,
+    LoadState,
+    PreviousSlot,
+    NextSlot,
+    RewindStart,
+    RewindStop,
+    TogglePause,
+    SpeedUp,
+    SlowDown,
+    TurboStart,
+    TurboStop,
 }
 
 pub struct SdlContext {
@@ -44,25 +380,37 @@ pub struct SdlContext {
     canvas: sdl2::render::Canvas<sdl2::video::Window>,
     pub event_pump: sdl2::EventPump,
     _desired_spec: AudioSpecDesired,
-    pub sound_device: AudioDevice<SquareWave>,
+    pub sound_device: AudioDevice<PatternWave>,
     pixel_size: u32,
+    pub palette: [Color; 1 << PLANES],
+    pub keymap: Keymap,
+    pads: Pads,
+    // cuántas teclas y botones tienen apretada cada tecla del CHIP-8
+    held: [u8; 16],
 }
 
-pub fn initialize_sdl(pixel_size: u32) -> Result<SdlContext, anyhow::Error> {
+pub fn initialize_sdl(pixel_size: u32, fullscreen: bool) -> Result<SdlContext, anyhow::Error> {
     let sdl_context = sdl2::init().map_err(|e| anyhow!(e))?;
     let video_subsystem = sdl_context.video().map_err(|e| anyhow!(e))?;
     let audio_subsystem = sdl_context.audio().map_err(|e| anyhow!(e))?;
+    let controller_subsystem = sdl_context.game_controller().map_err(|e| anyhow!(e))?;
 
-    let window = video_subsystem
-        .window("CHIP-8",
-            SCREEN_WIDTH as u32 * pixel_size,
-            SCREEN_HEIGHT as u32 * pixel_size)
-        .position_centered()
-        //.resizable()
-        .build()
-        .map_err(|e| anyhow!(e))?;
+    let width = SCREEN_WIDTH as u32 * pixel_size;
+    let height = SCREEN_HEIGHT as u32 * pixel_size;
+
+    let mut window = video_subsystem.window("CHIP-8", width, height);
+    window.position_centered();
+    if fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|e| anyhow!(e))?;
 
-    let canvas = window.into_canvas().build().map_err(|e| anyhow!(e))?;
+    let mut canvas = window.into_canvas().build().map_err(|e| anyhow!(e))?;
+    // se dibuja siempre en 128x64 y SDL lo escala a la ventana (con bordes
+    // si hace falta), así cualquier escala sirve
+    canvas
+        .set_logical_size(HIRES_SCREEN_WIDTH as u32, HIRES_SCREEN_HEIGHT as u32)
+        .map_err(|e| anyhow!(e))?;
     let event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
 
     let desired_spec = AudioSpecDesired {
@@ -72,10 +420,12 @@ pub fn initialize_sdl(pixel_size: u32) -> Result<SdlContext, anyhow::Error> {
     };
 
     let sound_device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-        SquareWave {
-            phase_inc: 800.0 / spec.freq as f32,
+        PatternWave {
+            pattern: None,
+            phase_inc: BEEP_FREQUENCY / spec.freq as f32,
             phase: 0.0,
-            volume: 0.25,
+            volume: MAX_VOLUME,
+            freq: spec.freq as f32,
         }
     }).map_err(|e| anyhow!(e))?;
 
@@ -87,7 +437,11 @@ pub fn initialize_sdl(pixel_size: u32) -> Result<SdlContext, anyhow::Error> {
         event_pump,
         _desired_spec: desired_spec,
         sound_device,
-        pixel_size
+        pixel_size,
+        palette: DEFAULT_PALETTE,
+        keymap: default_keymap(),
+        pads: Pads::new(controller_subsystem),
+        held: [0; 16],
     })
 }
 
@@ -96,29 +450,26 @@ pub fn render_window(
     sdl: &mut SdlContext,
     screen: &chip8::Screen
 ) -> Result<(), anyhow::Error> {
-    let white = Color::WHITE;
-    let black = Color::BLACK;
-
-    sdl.canvas.set_draw_color(black);
+    sdl.canvas.set_draw_color(sdl.palette[0]);
     sdl.canvas.clear();
 
 
+    // en lores cada pixel son 2x2 de hires
+    let pixel_size = (HIRES_SCREEN_WIDTH / screen.width()) as u32;
+
     //dibujar todo
-    for x in 0..SCREEN_WIDTH {
-        for y in 0..SCREEN_HEIGHT {
-            let color = match screen.get_pixel((x, y)) {
-                true => white,
-                false => black,
-            };
+    for x in 0..screen.width() {
+        for y in 0..screen.height() {
+            let color = sdl.palette[screen.get_color((x, y)) as usize];
 
             let x = x as u32;
             let y = y as u32;
 
             //esquinas del pixel
-            let x_1 = (x * sdl.pixel_size) as i16;
-            let x_2 = (x * sdl.pixel_size + sdl.pixel_size) as i16;
-            let y_1 = (y * sdl.pixel_size) as i16;
-            let y_2 = (y * sdl.pixel_size + sdl.pixel_size) as i16;
+            let x_1 = (x * pixel_size) as i16;
+            let x_2 = (x * pixel_size + pixel_size - 1) as i16;
+            let y_1 = (y * pixel_size) as i16;
+            let y_2 = (y * pixel_size + pixel_size - 1) as i16;
 
             sdl.canvas
                 .box_(x_1, y_1, x_2, y_2, color)
@@ -131,58 +482,199 @@ pub fn render_window(
     Ok(())
 }
 
-pub fn check_input(event_pump: &mut sdl2::EventPump) -> Option<Action> {
-    let keys: HashMap<Keycode, u8> = HashMap::from([
-        (Keycode::Num1, 0x1),
-        (Keycode::Num2, 0x2),
-        (Keycode::Num3, 0x3),
-        (Keycode::Num4, 0xC),
-        (Keycode::Q, 0x4),
-        (Keycode::W, 0x5),
-        (Keycode::E, 0x6),
-        (Keycode::R, 0xD),
-        (Keycode::A, 0x7),
-        (Keycode::S, 0x8),
-        (Keycode::D, 0x9),
-        (Keycode::F, 0xE),
-        (Keycode::Z, 0xA),
-        (Keycode::X, 0x0),
-        (Keycode::C, 0xB),
-        (Keycode::V, 0xF)
-    ]);
+pub fn set_title(sdl: &mut SdlContext, title: &str) {
+    if let Err(e) = sdl.canvas.window_mut().set_title(title) {
+        eprintln!("{}", e);
+    }
+}
 
+pub fn check_input(sdl: &mut SdlContext) -> Vec<Action> {
+    let keys = &sdl.keymap.keys;
+    let held = &mut sdl.held;
+    let pads = &mut sdl.pads;
+    let mut actions = Vec::new();
 
-    for event in event_pump.poll_iter() {
-        match event {
+    let pad_map = &sdl.keymap.pad;
+
+    // todos los eventos del frame, en orden; si no un tap rápido se pierde
+    for event in sdl.event_pump.poll_iter() {
+        let action = match event {
+            // las repeticiones del teclado no son teclas nuevas
+            Event::KeyDown { repeat: true, .. } => None,
             Event::Quit { .. } |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    return Some(Action::Quit)
-            },
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => Some(Action::Quit),
+            Event::KeyDown { keycode: Some(Keycode::F5), .. } => Some(Action::SaveState),
+            Event::KeyDown { keycode: Some(Keycode::F9), .. } => Some(Action::LoadState),
+            Event::KeyDown { keycode: Some(Keycode::F6), .. } => Some(Action::PreviousSlot),
+            Event::KeyDown { keycode: Some(Keycode::F7), .. } => Some(Action::NextSlot),
+            Event::KeyDown { keycode: Some(Keycode::F10), .. } => Some(Action::TogglePause),
+            Event::KeyDown {
+                keycode: Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus), ..
+            } => Some(Action::SpeedUp),
+            Event::KeyDown {
+                keycode: Some(Keycode::Minus | Keycode::KpMinus), ..
+            } => Some(Action::SlowDown),
+            Event::KeyDown { keycode: Some(Keycode::Tab), .. } => Some(Action::TurboStart),
+            Event::KeyUp { keycode: Some(Keycode::Tab), .. } => Some(Action::TurboStop),
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace), ..
+            } => Some(Action::RewindStart),
+            Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => Some(Action::RewindStop),
             Event::KeyDown { keycode: Some(key), .. } => {
-                return check_key_down(&keys, key)
-            }
+                keys.get(&key).and_then(|&k| hold(held, k, true))
+            },
             Event::KeyUp { keycode: Some(key), .. } => {
-                return check_key_up(&keys, key)
+                keys.get(&key).and_then(|&k| hold(held, k, false))
+            },
+            Event::ControllerDeviceAdded { which, .. } => {
+                pads.added(which);
+                None
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                actions.extend(pad_actions(held, pad_map, pads.removed(which)));
+                None
+            },
+            Event::ControllerButtonDown { which, button, .. } => {
+                actions.extend(pad_actions(held, pad_map, pads.button(which, button, true)));
+                None
+            },
+            Event::ControllerButtonUp { which, button, .. } => {
+                actions.extend(pad_actions(held, pad_map, pads.button(which, button, false)));
+                None
+            },
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                actions.extend(pad_actions(held, pad_map, pads.axis(which, axis, value)));
+                None
+            },
+            _ => None,
+        };
+        actions.extend(action);
+    }
+
+    actions
+}
+
+// de cambios en el joystick a teclas del CHIP-8
+fn pad_actions(
+    held: &mut [u8; 16],
+    pad_map: &HashMap<PadInput, u8>,
+    changes: Vec<(PadInput, bool)>
+) -> Vec<Action> {
+    changes
+        .into_iter()
+        .filter_map(|(input, down)| hold(held, *pad_map.get(&input)?, down))
+        .collect()
+}
+
+// Varias teclas y botones pueden ir a la misma tecla del CHIP-8: se aprieta
+// con el primero y se suelta cuando se sueltan todos
+fn hold(held: &mut [u8; 16], key: u8, down: bool) -> Option<Action> {
+    let count = &mut held[key as usize];
+    if down {
+        *count = count.saturating_add(1);
+        (*count == 1).then_some(Action::Press(key))
+    } else if *count > 0 {
+        *count -= 1;
+        (*count == 0).then_some(Action::Release(key))
+    } else {
+        None
+    }
+}
+
+// Lista para elegir una opción con las flechas (o el joystick) y Enter.
+// None si se cierra la ventana o se aprieta Escape.
+pub fn pick(sdl: &mut SdlContext, items: &[String]) -> Result<Option<usize>, anyhow::Error> {
+    // el texto va en pixels de la ventana, no del CHIP-8
+    let (chip8_width, chip8_height) = sdl.canvas.logical_size();
+    let width = SCREEN_WIDTH as u32 * sdl.pixel_size;
+    let height = SCREEN_HEIGHT as u32 * sdl.pixel_size;
+    sdl.canvas.set_logical_size(width, height).map_err(|e| anyhow!(e))?;
+
+    let picked = pick_from(sdl, items, width, height);
+    sdl.canvas.set_logical_size(chip8_width, chip8_height).map_err(|e| anyhow!(e))?;
+    picked
+}
+
+fn pick_from(
+    sdl: &mut SdlContext,
+    items: &[String],
+    width: u32,
+    height: u32
+) -> Result<Option<usize>, anyhow::Error> {
+    const ROW: u32 = 10;
+    let rows = (height / ROW).max(1) as usize;
+    let mut selected: usize = 0;
+
+    loop {
+        for event in sdl.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } |
+                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(None),
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } |
+                    Event::ControllerButtonDown { button: Button::DPadUp, .. } => {
+                    selected = selected.saturating_sub(1);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } |
+                    Event::ControllerButtonDown { button: Button::DPadDown, .. } => {
+                    selected = (selected + 1).min(items.len().saturating_sub(1));
+                },
+                Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } |
+                    Event::ControllerButtonDown { button: Button::A, .. } => {
+                    return Ok(Some(selected));
+                },
+                Event::ControllerDeviceAdded { which, .. } => sdl.pads.added(which),
+                _ => {},
             }
-            _ => {}
         }
+
+        sdl.canvas.set_draw_color(sdl.palette[0]);
+        sdl.canvas.clear();
+
+        // se desplaza para que la elegida siempre se vea
+        let first = selected.saturating_sub(rows - 1);
+        for (row, (i, item)) in items.iter().enumerate().skip(first).take(rows).enumerate() {
+            let y = (row as u32 * ROW) as i16;
+            let color = if i == selected {
+                sdl.canvas
+                    .box_(0, y, width as i16 - 1, y + ROW as i16 - 1, sdl.palette[1])
+                    .map_err(|e| anyhow!(e))?;
+                sdl.palette[0]
+            } else {
+                sdl.palette[1]
+            };
+            sdl.canvas.string(4, y + 1, item, color).map_err(|e| anyhow!(e))?;
+        }
+
+        sdl.canvas.present();
+        std::thread::sleep(std::time::Duration::from_millis(16));
     }
-    None
 }
 
-fn check_key_down(keys: &HashMap<Keycode, u8>, key: Keycode) -> Option<Action> {
-    keys.get(&key).map(|&x| Action::Press(x))
+// rate: bits del patrón por segundo
+pub fn update_sound(
+    sound_device: &mut AudioDevice<PatternWave>,
+    pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    rate: f32
+) {
+    let mut wave = sound_device.lock();
+
+    wave.phase_inc = match pattern {
+        Some(_) => rate / (AUDIO_PATTERN_SIZE * 8) as f32 / wave.freq,
+        None => BEEP_FREQUENCY / wave.freq,
+    };
+    wave.pattern = pattern;
 }
 
-fn check_key_up(keys: &HashMap<Keycode, u8>, key: Keycode) -> Option<Action> {
-    keys.get(&key).map(|&x| Action::Release(x))
+// volume entre 0 y 1
+pub fn set_volume(sound_device: &mut AudioDevice<PatternWave>, volume: f32) {
+    sound_device.lock().volume = volume.clamp(0.0, 1.0) * MAX_VOLUME;
 }
 
-pub fn play_sound(sound_device: &AudioDevice<SquareWave>) {
+pub fn play_sound(sound_device: &AudioDevice<PatternWave>) {
     sound_device.resume();
 }
 
-pub fn stop_sound(sound_device: &AudioDevice<SquareWave>) {
+pub fn stop_sound(sound_device: &AudioDevice<PatternWave>) {
     sound_device.pause();
 }
 
@@ -0,0 +1,33 @@
+pub mod assembler;
+pub mod chip8;
+pub mod database;
+pub mod debugger;
+pub mod disassembler;
+pub mod font;
+pub mod headless;
+pub mod loader;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod state;
+
+#[cfg(feature = "sdl")]
+pub mod game;
+#[cfg(feature = "sdl")]
+pub mod interface;
+
+pub use chip8::{
+    Chip8, Chip8Error, MemoryAccess, RomError, Screen, StepOutcome, AUDIO_PATTERN_SIZE,
+    DEFAULT_FONT_ADDRESS, DEFAULT_IPS, DEFAULT_LOAD_ADDRESS, FRAME, HIRES_SCREEN_HEIGHT,
+    HIRES_SCREEN_WIDTH, PLANES, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+pub use database::{Database, RomInfo};
+pub use debugger::Debugger;
+pub use disassembler::{disassemble, Instruction};
+pub use font::{Font, FontSet};
+pub use loader::{LoadError, Rom};
+pub use quirks::{QuirkProfile, Quirks};
+pub use rewind::Rewind;
+pub use rng::Rng;
+pub use state::StateError;
@@ -0,0 +1,142 @@
+use crate::chip8::MEMORY_SIZE;
+use std::io::{Cursor, Read};
+use thiserror::Error;
+
+// Lo que hay antes de Chip8::load_rom_bytes: una ROM suelta o una ROM
+// dentro de un zip (si hay varias se elige por nombre).
+
+pub const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("can't read the ROM: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad zip archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("the zip has no ROMs (.ch8, .sc8 or .xo8)")]
+    NoRoms,
+    #[error("the zip has no ROM named '{0}'")]
+    NoSuchRom(String),
+    #[error("the zip has several ROMs, choose one with --rom: {}", .0.join(", "))]
+    SeveralRoms(Vec<String>),
+    #[error("the ROM doesn't fit in memory (more than 64 KB)")]
+    TooLarge,
+    #[error("--rom only makes sense with a zip archive")]
+    NotAZip,
+}
+
+pub struct Rom {
+    // para mostrar y para los estados guardados: el archivo, o para un zip
+    // "archivo#rom"
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06")
+}
+
+fn is_rom(name: &str) -> bool {
+    let extension = name.rsplit_once('.').map_or("", |(_, e)| e);
+    ROM_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(extension))
+}
+
+// las ROMs del zip en el orden en que están
+pub fn zip_roms(data: &[u8]) -> Result<Vec<String>, LoadError> {
+    let archive = zip::ZipArchive::new(Cursor::new(data))?;
+    Ok(archive.file_names().filter(|name| is_rom(name)).map(String::from).collect())
+}
+
+// `entry` elige la ROM dentro de un zip, con la ruta completa o sólo el
+// nombre del archivo; si el zip tiene una sola no hace falta
+pub fn load(file: &str, entry: Option<&str>) -> Result<Rom, LoadError> {
+    load_bytes(file, std::fs::read(file)?, entry)
+}
+
+pub fn load_bytes(file: &str, data: Vec<u8>, entry: Option<&str>) -> Result<Rom, LoadError> {
+    if !is_zip(&data) {
+        if entry.is_some() {
+            return Err(LoadError::NotAZip);
+        }
+        return Ok(Rom { name: file.to_string(), data });
+    }
+
+    let roms = zip_roms(&data)?;
+    let name = match (entry, roms.as_slice()) {
+        (None, []) => return Err(LoadError::NoRoms),
+        (None, [only]) => only.clone(),
+        (None, _) => return Err(LoadError::SeveralRoms(roms)),
+        (Some(entry), _) => roms
+            .iter()
+            .find(|rom| *rom == entry || file_name(rom) == entry)
+            .ok_or_else(|| LoadError::NoSuchRom(entry.to_string()))?
+            .clone(),
+    };
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(&data))?;
+    // el tamaño que dice el zip puede ser mentira
+    let mut rom = Vec::new();
+    archive.by_name(&name)?.take(MEMORY_SIZE as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > MEMORY_SIZE {
+        return Err(LoadError::TooLarge);
+    }
+
+    Ok(Rom { name: format!("{}#{}", file, file_name(&name)), data: rom })
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn roms_in_a_zip() {
+        let pack = zip(&[
+            ("README.txt", b"hi"),
+            ("games/pong.ch8", b"\x00\xe0"),
+            ("tank.SC8", b"\x12\x00"),
+        ]);
+        assert_eq!(zip_roms(&pack).unwrap(), ["games/pong.ch8", "tank.SC8"]);
+
+        let rom = load_bytes("pack.zip", pack.clone(), Some("pong.ch8")).unwrap();
+        assert_eq!(rom.name, "pack.zip#pong.ch8");
+        assert_eq!(rom.data, b"\x00\xe0");
+        let rom = load_bytes("pack.zip", pack.clone(), Some("tank.SC8")).unwrap();
+        assert_eq!(rom.data, b"\x12\x00");
+
+        let several = load_bytes("pack.zip", pack.clone(), None);
+        assert!(matches!(several, Err(LoadError::SeveralRoms(_))));
+        let missing = load_bytes("pack.zip", pack, Some("README.txt"));
+        assert!(matches!(missing, Err(LoadError::NoSuchRom(_))));
+
+        let single = zip(&[("pong.ch8", b"\x00\xe0")]);
+        assert_eq!(load_bytes("one.zip", single, None).unwrap().data, b"\x00\xe0");
+        assert!(matches!(load_bytes("empty.zip", zip(&[]), None), Err(LoadError::NoRoms)));
+
+        let plain = load_bytes("pong.ch8", b"\x00\xe0".to_vec(), None).unwrap();
+        assert_eq!(plain.name, "pong.ch8");
+        assert!(matches!(load_bytes("pong.ch8", plain.data, Some("x")), Err(LoadError::NotAZip)));
+    }
+
+    #[test]
+    fn zip_roms_must_fit_in_memory() {
+        let full = vec![0; MEMORY_SIZE];
+        assert_eq!(load_bytes("a.zip", zip(&[("a.ch8", &full)]), None).unwrap().data, full);
+
+        let big = zip(&[("big.ch8", &[0; MEMORY_SIZE + 1])]);
+        assert!(matches!(load_bytes("big.zip", big, None), Err(LoadError::TooLarge)));
+    }
+}
@@ -1,14 +1,302 @@
-mod chip8;
-mod game;
-mod interface;
-
+use chip_8::game::{self, Options};
+use chip_8::database::{self, Database};
+use chip_8::loader::{self, Rom};
+use chip_8::{headless, interface, Chip8, Font, FontSet, Rng};
 use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+usage: chip-8 [options] <rom>
+
+the ROM can also be in a zip archive
+
+options:
+  --scale N          window pixels per CHIP-8 pixel, 1 to 64 (default 8)
+  --ips N            instructions per second, 60 to 100000 (default 480)
+  --quirks PROFILE   vip, chip48, schip, xochip or modern (default modern)
+  --palette P        default, green, amber or a list of RRGGBB colors
+  --mute             no sound
+  --volume N         sound volume, 0 to 100 (default 100)
+  --fullscreen       start in fullscreen
+  --seed N           seed for the random number generator (Cxkk)
+  --font FONT        vip, dream6800, eti660, schip or a font file with the
+                     16 small glyphs (80 bytes) and optionally the 10 big
+                     ones after them (180 bytes) (default vip)
+  --font-address A   where the font goes in memory, in hex (default 000)
+  --load-address A   where the ROM is loaded and starts, in hex (default 200,
+                     ETI-660 ROMs use 600)
+  --rom NAME         the ROM to run from a zip with several (otherwise it asks
+                     in the window)
+  --headless         run without a window and print the final screen
+  --frames N         frames to run with --headless (default 600)
+  --debug            start paused in the debugger
+  --keymap FILE      keyboard and controller mapping file (lines like
+                     \"5 = W, Up, pad:dpup\", with [ROM SHA-1] sections for
+                     a single ROM)
+  --record FILE      record the keyboard input to a movie file
+  --replay FILE      play back a movie file (its seed, quirks and speed win)
+  --database DIR     chip-8-database directory with programs.json and
+                     sha1-hashes.json (default ~/.local/share/chip-8/database)
+  --rom-config FILE  your own settings per ROM, same format as the database
+                     ROMs (default ~/.config/chip-8/roms.json)
+  --no-database      don't look up the ROM; otherwise its platform, quirks,
+                     speed, load address, colors and keys are used unless
+                     given here
+  -h, --help         show this help
+  -V, --version      show the version";
+
+struct Cli {
+    file: String,
+    // dentro de un zip
+    rom: Option<String>,
+    options: Options,
+    headless: bool,
+    frames: u32,
+    database: Option<PathBuf>,
+    rom_config: Option<PathBuf>,
+    no_database: bool,
+}
+
+fn address(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u16, String> {
+    let s = value(args, flag)?;
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(hex, 16).map_err(|_| format!("invalid value '{}' for {}", s, flag))
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn number<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str
+) -> Result<T, String> {
+    let s = value(args, flag)?;
+    s.parse().map_err(|_| format!("invalid value '{}' for {}", s, flag))
+}
 
+// un nombre de fuente, o si no es ninguno un archivo
+fn font(name: &str) -> Result<Font, String> {
+    match name.parse::<FontSet>() {
+        Ok(set) => Ok(set.font()),
+        Err(e) if !Path::new(name).exists() => Err(e.to_string()),
+        Err(_) => Font::load(name).map_err(|e| format!("{}: {}", name, e)),
+    }
+}
+
+// `options` son los valores por defecto, lo que se pase acá los pisa
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+    mut options: Options
+) -> Result<Option<Cli>, String> {
+    let mut file = None;
+    let mut rom = None;
+    let mut headless = false;
+    let mut frames = 600;
+    let (mut database, mut rom_config, mut no_database) = (None, None, false);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                options.scale = number(&mut args, "--scale")?;
+                if !(1..=game::MAX_SCALE).contains(&options.scale) {
+                    return Err(format!("--scale goes from 1 to {}", game::MAX_SCALE));
+                }
+            },
+            "--ips" => {
+                options.ips = number(&mut args, "--ips")?;
+                if !(game::MIN_IPS..=game::MAX_IPS).contains(&options.ips) {
+                    return Err(format!("--ips goes from {} to {}", game::MIN_IPS, game::MAX_IPS));
+                }
+            },
+            "--quirks" => {
+                let profile: chip_8::QuirkProfile = value(&mut args, "--quirks")?
+                    .parse()
+                    .map_err(|e| format!("{}", e))?;
+                options.quirks = profile.into();
+            },
+            "--palette" => {
+                options.palette = interface::parse_palette(&value(&mut args, "--palette")?)
+                    .map_err(|e| format!("{}", e))?;
+            },
+            "--mute" => options.mute = true,
+            "--volume" => {
+                let volume: u8 = number(&mut args, "--volume")?;
+                if volume > 100 {
+                    return Err("--volume goes from 0 to 100".to_string());
+                }
+                options.volume = volume as f32 / 100.0;
+            },
+            "--fullscreen" => options.fullscreen = true,
+            "--seed" => options.seed = Some(number(&mut args, "--seed")?),
+            "--font" => options.font = font(&value(&mut args, "--font")?)?,
+            "--font-address" => options.font_address = address(&mut args, "--font-address")?,
+            "--load-address" => options.load_address = address(&mut args, "--load-address")?,
+            "--rom" => rom = Some(value(&mut args, "--rom")?),
+            "--headless" => headless = true,
+            "--frames" => frames = number(&mut args, "--frames")?,
+            "--debug" => options.debug = true,
+            "--keymap" => options.keymap = Some(value(&mut args, "--keymap")?),
+            "--record" => options.record = Some(value(&mut args, "--record")?),
+            "--replay" => options.replay = Some(value(&mut args, "--replay")?),
+            "--database" => database = Some(value(&mut args, "--database")?.into()),
+            "--rom-config" => rom_config = Some(value(&mut args, "--rom-config")?.into()),
+            "--no-database" => no_database = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(None);
+            },
+            "-V" | "--version" => {
+                println!("chip-8 {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if file.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => file = Some(arg),
+        }
+    }
+
+    let file = file.ok_or_else(|| "missing ROM file".to_string())?;
+    if options.debug && (options.record.is_some() || options.replay.is_some()) {
+        return Err("--debug can't be used with --record or --replay".to_string());
+    }
+
+    Ok(Some(Cli { file, rom, options, headless, frames, database, rom_config, no_database }))
+}
+
+// Si es un zip con varias ROMs y no se dijo cuál, se elige en la ventana.
+// None si no se eligió ninguna.
+fn open_rom(cli: &Cli) -> Result<Option<Rom>, anyhow::Error> {
+    let data = std::fs::read(&cli.file).map_err(|e| anyhow::anyhow!("{}: {}", cli.file, e))?;
+    let mut entry = cli.rom.clone();
+
+    if entry.is_none() && !cli.headless && loader::is_zip(&data) {
+        let roms = loader::zip_roms(&data).map_err(|e| anyhow::anyhow!("{}: {}", cli.file, e))?;
+        if roms.len() > 1 {
+            match game::pick_rom(&cli.file, &roms, &cli.options)? {
+                Some(rom) => entry = Some(rom),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    let rom = loader::load_bytes(&cli.file, data, entry.as_deref())
+        .map_err(|e| anyhow::anyhow!("{}: {}", cli.file, e))?;
+    Ok(Some(rom))
+}
+
+// Las opciones por defecto para esta ROM según la base de datos. Los
+// archivos por defecto pueden no estar, los que se pasan a mano no.
+fn rom_options(cli: &Cli, rom: &Rom) -> Result<Options, anyhow::Error> {
+    let mut options = Options::default();
+    if cli.no_database {
+        return Ok(options);
+    }
+
+    let hash = database::rom_hash(&rom.data);
+
+    let dir = cli.database
+        .clone()
+        .or_else(|| database::default_dir().filter(|d| d.exists()));
+    let mut db = match dir {
+        Some(dir) => Database::load_dir(&dir)?,
+        None => Database::new(),
+    };
+
+    let rom_config = cli.rom_config
+        .clone()
+        .or_else(|| database::default_overrides().filter(|f| f.exists()));
+    if let Some(path) = rom_config {
+        db.load_overrides(&path)?;
+    }
+
+    let Some(info) = db.lookup(&hash) else {
+        return Ok(options);
+    };
+    eprintln!(
+        "{} ({})",
+        info.title.as_deref().unwrap_or("Unknown ROM"),
+        info.platform.as_deref().unwrap_or("unknown platform")
+    );
+
+    if let Some(quirks) = info.quirks {
+        options.quirks = quirks;
+    }
+    if let Some(ips) = info.ips {
+        options.ips = ips.clamp(game::MIN_IPS, game::MAX_IPS);
+        if options.ips != ips {
+            eprintln!("The ROM's speed ({} IPS) is out of range, using {}", ips, options.ips);
+        }
+    }
+    if let Some(address) = info.load_address {
+        options.load_address = address;
+    }
+    if !info.colors.is_empty() {
+        match interface::parse_palette(&info.colors.join(",")) {
+            Ok(palette) => options.palette = palette,
+            Err(e) => eprintln!("Ignoring the ROM's colors: {}", e),
+        }
+    }
+    options.key_hints = info.keys;
+
+    Ok(options)
+}
+
+fn run_headless(cli: &Cli, rom: &Rom) -> Result<(), anyhow::Error> {
+    let seed = cli.options.seed.unwrap_or_else(rand::random);
+    eprintln!("Seed: {}", seed);
+
+    let mut chip8 = Chip8::with_rng(cli.options.quirks, Rng::from_state(seed));
+    chip8.set_font(&cli.options.font, cli.options.font_address)?;
+    chip8.set_load_address(cli.options.load_address);
+    chip8.load_rom_bytes(&rom.data)?;
+    chip8.set_instructions_per_second(cli.options.ips);
+
+    headless::run(&mut chip8, cli.frames, &[])?;
+    print!("{}", headless::ascii(&chip8.screen));
+
+    Ok(())
+}
 
 fn main() {
-    let file = env::args().nth(1).unwrap_or("game.ch8".to_string());
+    let args: Vec<String> = env::args().skip(1).collect();
+    let parse = |options| match parse_args(args.iter().cloned(), options) {
+        Ok(Some(cli)) => cli,
+        Ok(None) => process::exit(0),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // primero para saber qué ROM es, después otra vez sobre lo que dice la
+    // base de datos
+    let cli = parse(Options::default());
+    let rom = match open_rom(&cli) {
+        Ok(Some(rom)) => rom,
+        Ok(None) => process::exit(0),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let cli = match rom_options(&cli, &rom) {
+        Ok(options) => parse(options),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let result = if cli.headless {
+        run_headless(&cli, &rom)
+    } else {
+        game::run(&rom, &cli.options)
+    };
 
-    if let Err(e) = game::run(&file) {
+    if let Err(e) = result {
         eprintln!("{}", e);
+        process::exit(1);
     }
 }
@@ -0,0 +1,321 @@
+use crate::chip8::{DEFAULT_FONT_ADDRESS, DEFAULT_LOAD_ADDRESS};
+use crate::font::Font;
+use crate::quirks::{QuirkProfile, Quirks, UnknownProfile};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Grabación de las entradas de una partida, para reproducirla exactamente
+// igual. Es texto para poder adjuntarla a un reporte de bug:
+//
+//     chip8-movie 2
+//     rom 2fd4e1c67a2d28fced849ee1bb76e7391b93eb12
+//     seed 1234
+//     quirks schip
+//     ips 480
+//     font f0909090f02060202070...
+//     font-address 000
+//     load-address 200
+//     120 press 5
+//     128 release 5
+//     300 ips 600
+//     end 900
+//
+// Cada evento se aplica antes de correr el frame con ese número. Si las
+// quirks no son las de un perfil van las que están activas, p. ej.
+// "quirks custom shift_uses_vy clip_sprites".
+
+const MAGIC: &str = "chip8-movie";
+const VERSION: u32 = 2;
+
+// nombre de cada quirk en el archivo
+fn quirk_flags(quirks: &mut Quirks) -> [(&'static str, &mut bool); 8] {
+    [
+        ("shift_uses_vy", &mut quirks.shift_uses_vy),
+        ("load_store_increments_index", &mut quirks.load_store_increments_index),
+        ("jump_uses_vx", &mut quirks.jump_uses_vx),
+        ("logic_resets_vf", &mut quirks.logic_resets_vf),
+        ("clip_sprites", &mut quirks.clip_sprites),
+        ("display_wait", &mut quirks.display_wait),
+        ("wait_for_release", &mut quirks.wait_for_release),
+        ("large_memory", &mut quirks.large_memory),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Press(u8),
+    Release(u8),
+    // cambio de velocidad con las teclas +/-
+    Speed(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub input: Input,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub ips: u32,
+    pub font: Font,
+    pub font_address: u16,
+    pub load_address: u16,
+    events: Vec<MovieEvent>,
+    // cantidad de frames grabados
+    length: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum MovieError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("not a movie file")]
+    BadMagic,
+    #[error("unsupported movie version {0}")]
+    UnsupportedVersion(u32),
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("missing '{0}' in movie header")]
+    Missing(&'static str),
+}
+
+impl Movie {
+    // la fuente y las direcciones quedan las de siempre
+    pub fn new(rom_hash: &str, seed: u64, quirks: Quirks, ips: u32) -> Movie {
+        Movie {
+            rom_hash: rom_hash.to_string(),
+            seed,
+            quirks,
+            ips,
+            font: Font::default(),
+            font_address: DEFAULT_FONT_ADDRESS,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            events: Vec::new(),
+            length: 0,
+        }
+    }
+
+    // los frames tienen que ir en orden
+    pub fn record(&mut self, frame: u64, input: Input) {
+        debug_assert!(frame >= self.length);
+        self.events.push(MovieEvent { frame, input });
+        self.length = frame;
+    }
+
+    pub fn finish(&mut self, frames: u64) {
+        self.length = self.length.max(frames);
+    }
+
+    pub fn events(&self) -> &[MovieEvent] {
+        &self.events
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn load(path: &str) -> Result<Movie, MovieError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), MovieError> {
+        Ok(std::fs::write(path, self.to_string())?)
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        writeln!(f, "rom {}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        match QuirkProfile::matching(self.quirks) {
+            Some(profile) => writeln!(f, "quirks {}", profile)?,
+            None => {
+                write!(f, "quirks custom")?;
+                for (name, on) in quirk_flags(&mut self.quirks.clone()) {
+                    if *on {
+                        write!(f, " {}", name)?;
+                    }
+                }
+                writeln!(f)?;
+            },
+        }
+        writeln!(f, "ips {}", self.ips)?;
+        write!(f, "font ")?;
+        for byte in self.font.small.iter().chain(&self.font.big) {
+            write!(f, "{:02x}", byte)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "font-address {:03x}", self.font_address)?;
+        writeln!(f, "load-address {:03x}", self.load_address)?;
+
+        for event in &self.events {
+            match event.input {
+                Input::Press(key) => writeln!(f, "{} press {:X}", event.frame, key)?,
+                Input::Release(key) => writeln!(f, "{} release {:X}", event.frame, key)?,
+                Input::Speed(ips) => writeln!(f, "{} ips {}", event.frame, ips)?,
+            }
+        }
+
+        writeln!(f, "end {}", self.length)
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(n, line)| (n + 1, line.trim()));
+
+        let version = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix(MAGIC))
+            .ok_or(MovieError::BadMagic)?;
+        let version = version.trim().parse().map_err(|_| MovieError::BadMagic)?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let (mut rom_hash, mut seed, mut quirks, mut ips) = (None, None, None, None);
+        let (mut font, mut font_address, mut load_address) = (None, None, None);
+        let mut events = Vec::new();
+        let mut length = None;
+
+        for (line, text) in lines {
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            let err = |message: &str| MovieError::Syntax { line, message: message.to_string() };
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let address = |hex: &str| {
+                u16::from_str_radix(hex, 16).map_err(|_| err("invalid address"))
+            };
+
+            match words.as_slice() {
+                ["rom", hash] => rom_hash = Some(hash.to_ascii_lowercase()),
+                ["seed", n] => seed = Some(n.parse().map_err(|_| err("invalid seed"))?),
+                ["quirks", "custom", flags @ ..] => {
+                    let mut custom = Quirks::MODERN;
+                    for (_, on) in quirk_flags(&mut custom) {
+                        *on = false;
+                    }
+                    for flag in flags {
+                        let (_, on) = quirk_flags(&mut custom)
+                            .into_iter()
+                            .find(|(name, _)| name == flag)
+                            .ok_or_else(|| err(&format!("unknown quirk '{}'", flag)))?;
+                        *on = true;
+                    }
+                    quirks = Some(custom);
+                },
+                ["quirks", name] => {
+                    let profile: QuirkProfile =
+                        name.parse().map_err(|e: UnknownProfile| err(&e.to_string()))?;
+                    quirks = Some(profile.quirks());
+                },
+                ["ips", n] => ips = Some(n.parse().map_err(|_| err("invalid ips"))?),
+                ["font", hex] => {
+                    let bytes = (0..hex.len())
+                        .step_by(2)
+                        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                        .collect::<Option<Vec<u8>>>()
+                        .ok_or_else(|| err("invalid font"))?;
+                    font = Some(Font::from_bytes(&bytes).map_err(|e| err(&e.to_string()))?);
+                },
+                ["font-address", a] => font_address = Some(address(a)?),
+                ["load-address", a] => load_address = Some(address(a)?),
+                ["end", n] => length = Some(n.parse().map_err(|_| err("invalid frame"))?),
+                [frame, kind, value] => {
+                    let frame: u64 = frame.parse().map_err(|_| err("invalid frame"))?;
+                    if events.last().is_some_and(|e: &MovieEvent| e.frame > frame) {
+                        return Err(err("events out of order"));
+                    }
+
+                    let key = || {
+                        u8::from_str_radix(value, 16)
+                            .ok()
+                            .filter(|&k| k < 16)
+                            .ok_or_else(|| err("invalid key"))
+                    };
+                    let input = match *kind {
+                        "press" => Input::Press(key()?),
+                        "release" => Input::Release(key()?),
+                        "ips" => Input::Speed(value.parse().map_err(|_| err("invalid ips"))?),
+                        _ => return Err(err("unknown event")),
+                    };
+
+                    events.push(MovieEvent { frame, input });
+                },
+                _ => return Err(err("unexpected line")),
+            }
+        }
+
+        let last = events.last().map_or(0, |e| e.frame);
+
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or(MovieError::Missing("rom"))?,
+            seed: seed.ok_or(MovieError::Missing("seed"))?,
+            quirks: quirks.ok_or(MovieError::Missing("quirks"))?,
+            ips: ips.ok_or(MovieError::Missing("ips"))?,
+            font: font.ok_or(MovieError::Missing("font"))?,
+            font_address: font_address.ok_or(MovieError::Missing("font-address"))?,
+            load_address: load_address.ok_or(MovieError::Missing("load-address"))?,
+            events,
+            length: length.unwrap_or(last).max(last),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::FontSet;
+
+    fn movie(quirks: Quirks) -> Movie {
+        let mut movie = Movie::new("2fd4e1c67a2d28fced849ee1bb76e7391b93eb12", 1234, quirks, 600);
+        movie.font = FontSet::SuperChip.font();
+        movie.font_address = 0x50;
+        movie.load_address = 0x600;
+        movie.record(10, Input::Press(0xA));
+        movie.record(10, Input::Release(0xA));
+        movie.record(30, Input::Speed(750));
+        movie.finish(90);
+        movie
+    }
+
+    #[test]
+    fn parse_what_was_written() {
+        let custom = Quirks { clip_sprites: false, large_memory: true, ..Quirks::SUPER_CHIP };
+        for quirks in [Quirks::COSMAC_VIP, custom] {
+            let movie = movie(quirks);
+            assert_eq!(movie.to_string().parse::<Movie>().unwrap(), movie);
+        }
+        assert!(movie(custom).to_string().contains("quirks custom jump_uses_vx"));
+    }
+
+    #[test]
+    fn bad_movies() {
+        let header = "chip8-movie 2\nrom abc\nseed 5\nips 480\n";
+        let error = |text: &str| text.parse::<Movie>().unwrap_err().to_string();
+        assert_eq!(error("chip8-movie 1\n"), "unsupported movie version 1");
+        assert_eq!(error("chip8-movie 3\n"), "unsupported movie version 3");
+        assert_eq!(error("hello\n"), "not a movie file");
+        assert_eq!(error(header), "missing 'quirks' in movie header");
+        let with = |rest: &str| error(&format!("{}{}", header, rest));
+        assert_eq!(with("quirks custom fast\n"), "line 5: unknown quirk 'fast'");
+        assert!(with("quirks vip\nfont 12\n").starts_with("line 6: a font file has 80 bytes"));
+        assert_eq!(with("quirks vip\nfont-address x\n"), "line 6: invalid address");
+        assert_eq!(with("quirks vip\n9 press 1\n3 press 2\n"), "line 7: events out of order");
+        assert_eq!(with("quirks vip\n"), "missing 'font' in movie header");
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Comportamientos ambiguos de algunas instrucciones, que cambian según el
+// intérprete original para el que se escribió cada ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy6/8xyE: VX = VY >> 1 (o << 1) en vez de desplazar VX
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65: index queda en index + X + 1
+    pub load_store_increments_index: bool,
+    // Bxnn: salta a xnn + VX en vez de nnn + V0
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3: VF = 0
+    pub logic_resets_vf: bool,
+    // Dxyn: los sprites se cortan en el borde en vez de dar la vuelta
+    pub clip_sprites: bool,
+    // Dxyn: se dibuja como mucho un sprite por frame (espera al vblank)
+    pub display_wait: bool,
+    // Fx0A: termina cuando se suelta la tecla y no cuando se aprieta, como
+    // en el VIP (todos los perfiles lo hacen así)
+    pub wait_for_release: bool,
+    // 64 KB de memoria en vez de 4 KB
+    pub large_memory: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+        wait_for_release: true,
+        large_memory: false,
+    };
+
+    // CHIP-48 en realidad incrementaba index en X, no en X + 1
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: true,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        wait_for_release: true,
+        large_memory: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: false,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        wait_for_release: true,
+        large_memory: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        wait_for_release: true,
+        large_memory: true,
+    };
+
+    pub const MODERN: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: false,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        wait_for_release: true,
+        large_memory: false,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::MODERN
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirkProfile {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+    Modern,
+}
+
+impl QuirkProfile {
+    pub const ALL: [QuirkProfile; 5] = [
+        QuirkProfile::CosmacVip,
+        QuirkProfile::Chip48,
+        QuirkProfile::SuperChip,
+        QuirkProfile::XoChip,
+        QuirkProfile::Modern,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirkProfile::CosmacVip => "vip",
+            QuirkProfile::Chip48 => "chip48",
+            QuirkProfile::SuperChip => "schip",
+            QuirkProfile::XoChip => "xochip",
+            QuirkProfile::Modern => "modern",
+        }
+    }
+
+    // el perfil con exactamente estas quirks, si hay alguno
+    pub fn matching(quirks: Quirks) -> Option<QuirkProfile> {
+        QuirkProfile::ALL.into_iter().find(|p| p.quirks() == quirks)
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirkProfile::CosmacVip => Quirks::COSMAC_VIP,
+            QuirkProfile::Chip48 => Quirks::CHIP_48,
+            QuirkProfile::SuperChip => Quirks::SUPER_CHIP,
+            QuirkProfile::XoChip => Quirks::XO_CHIP,
+            QuirkProfile::Modern => Quirks::MODERN,
+        }
+    }
+}
+
+impl From<QuirkProfile> for Quirks {
+    fn from(profile: QuirkProfile) -> Self {
+        profile.quirks()
+    }
+}
+
+impl fmt::Display for QuirkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown quirk profile '{0}' (expected vip, chip48, schip, xochip or modern)")]
+pub struct UnknownProfile(String);
+
+impl FromStr for QuirkProfile {
+    type Err = UnknownProfile;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Ok(QuirkProfile::CosmacVip),
+            "chip48" | "chip-48" => Ok(QuirkProfile::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(QuirkProfile::SuperChip),
+            "xochip" | "xo-chip" | "octo" => Ok(QuirkProfile::XoChip),
+            "modern" => Ok(QuirkProfile::Modern),
+            _ => Err(UnknownProfile(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::chip8::Chip8;
+use crate::state::StateError;
+use std::collections::VecDeque;
+
+enum Patch {
+    // bytes que cambiaron, [offset: u32][largo: u16][bytes]...
+    Diff(Vec<u8>),
+    // si los estados tienen distinto tamaño se guarda entero
+    Full(Vec<u8>),
+}
+
+// Buffer circular de frames para volver atrás en el tiempo. Se guarda el
+// último estado completo y, para cada frame anterior, sólo los bytes que
+// cambiaron (la mayor parte de la memoria queda igual de un frame al otro).
+pub struct Rewind {
+    patches: VecDeque<Patch>,
+    capacity: usize,
+    current: Option<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            patches: VecDeque::with_capacity(capacity),
+            capacity,
+            current: None,
+        }
+    }
+
+    // llamar una vez por frame
+    pub fn record(&mut self, chip8: &Chip8) {
+        let state = chip8.save_state();
+
+        if let Some(current) = self.current.take() {
+            self.patches.push_back(diff(&state, &current));
+            if self.patches.len() > self.capacity {
+                self.patches.pop_front();
+            }
+        }
+        self.current = Some(state);
+    }
+
+    // vuelve un frame para atrás, false si no queda nada guardado; las
+    // teclas quedan como están
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> Result<bool, StateError> {
+        let (Some(patch), Some(current)) = (self.patches.pop_back(), self.current.as_mut()) else {
+            return Ok(false);
+        };
+
+        apply(current, &patch);
+        chip8.load_state_keeping_keys(current)?;
+
+        Ok(true)
+    }
+
+    pub fn clear(&mut self) {
+        self.patches.clear();
+        self.current = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+}
+
+// patch para pasar de `from` a `to`
+fn diff(from: &[u8], to: &[u8]) -> Patch {
+    if from.len() != to.len() {
+        return Patch::Full(to.to_vec());
+    }
+
+    let mut patch = Vec::new();
+    let mut i = 0;
+
+    while i < to.len() {
+        if from[i] == to[i] {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < to.len() && from[i] != to[i] && i - start < u16::MAX as usize {
+            i += 1;
+        }
+
+        patch.extend_from_slice(&(start as u32).to_le_bytes());
+        patch.extend_from_slice(&((i - start) as u16).to_le_bytes());
+        patch.extend_from_slice(&to[start..i]);
+    }
+
+    Patch::Diff(patch)
+}
+
+fn apply(state: &mut Vec<u8>, patch: &Patch) {
+    let mut patch = match patch {
+        Patch::Full(full) => {
+            state.clone_from(full);
+            return;
+        },
+        Patch::Diff(diff) => diff.as_slice(),
+    };
+
+    while patch.len() >= 6 {
+        let start = u32::from_le_bytes([patch[0], patch[1], patch[2], patch[3]]) as usize;
+        let len = u16::from_le_bytes([patch[4], patch[5]]) as usize;
+
+        state[start..start + len].copy_from_slice(&patch[6..6 + len]);
+        patch = &patch[6 + len..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+    use crate::rng::Rng;
+
+    // un frame por llamada a record, cada uno con la pantalla y V1 distintos
+    fn frames(count: usize) -> (Chip8, Vec<Vec<u8>>) {
+        // loop: V1 = random; dibujar en (V0, V0); V0 += 1
+        let program = [0xc1, 0xff, 0xa2, 0x0a, 0xd0, 0x01, 0x70, 0x01, 0x12, 0x00, 0xf0];
+        let mut c = Chip8::with_rng(Quirks::MODERN, Rng::from_state(3));
+        c.load_rom_bytes(&program).unwrap();
+
+        let mut states = Vec::new();
+        for _ in 0..count {
+            for _ in 0..5 {
+                c.step().unwrap();
+            }
+            states.push(c.save_state());
+        }
+        (c, states)
+    }
+
+    #[test]
+    fn rewind_restores_earlier_frames() {
+        let (mut c, states) = frames(10);
+        let mut rewind = Rewind::new(20);
+        let mut recorder = Chip8::new();
+        for state in &states {
+            recorder.load_state(state).unwrap();
+            rewind.record(&recorder);
+        }
+        assert_eq!(rewind.len(), 9);
+
+        for expected in states.iter().rev().skip(1) {
+            assert_eq!(rewind.rewind(&mut c), Ok(true));
+            assert_eq!(&c.save_state(), expected);
+        }
+        assert_eq!(rewind.rewind(&mut c), Ok(false));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn rewind_keeps_the_last_frames_when_full() {
+        let (mut c, states) = frames(12);
+        let mut rewind = Rewind::new(4);
+        let mut recorder = Chip8::new();
+        for state in &states {
+            recorder.load_state(state).unwrap();
+            rewind.record(&recorder);
+        }
+        assert_eq!(rewind.len(), 4);
+
+        for expected in states[7..11].iter().rev() {
+            assert_eq!(rewind.rewind(&mut c), Ok(true));
+            assert_eq!(&c.save_state(), expected);
+        }
+        assert_eq!(rewind.rewind(&mut c), Ok(false));
+
+        // se puede seguir grabando desde donde quedó
+        rewind.record(&c);
+        rewind.record(&recorder);
+        assert_eq!(rewind.rewind(&mut c), Ok(true));
+        assert_eq!(c.save_state(), states[7]);
+    }
+
+    // una tecla apretada en el frame guardado y suelta ahora no queda trabada
+    #[test]
+    fn rewind_keeps_the_live_keys() {
+        let (mut c, _) = frames(1);
+        let mut rewind = Rewind::new(4);
+        c.key_press(5);
+        let saved = c.save_state();
+        rewind.record(&c);
+        rewind.record(&c);
+
+        c.key_release(5);
+        c.key_press(6);
+        assert_eq!(rewind.rewind(&mut c), Ok(true));
+        assert!(c.key_just_released(5));
+        assert!(c.key_just_pressed(6));
+
+        let mut expected = Chip8::new();
+        expected.load_state(&saved).unwrap();
+        expected.key_release(5);
+        expected.key_press(6);
+        assert_eq!(c.save_state(), expected.save_state());
+    }
+
+    #[test]
+    fn diff_and_apply() {
+        let from = vec![0; 0x20000];
+        let mut to = from.clone();
+        to[3] = 1;
+        to[0x100..0x100 + 0x18000].fill(2);
+        to[0x1ffff] = 3;
+
+        let mut state = from.clone();
+        apply(&mut state, &diff(&from, &to));
+        assert_eq!(state, to);
+
+        let mut state = from.clone();
+        apply(&mut state, &diff(&from, &[1, 2, 3]));
+        assert_eq!(state, [1, 2, 3]);
+    }
+}
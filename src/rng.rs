@@ -0,0 +1,37 @@
+// Generador chico (xorshift64*) para Cxkk. Se usa uno propio en vez de
+// rand::random para poder guardar su estado en los save states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new() -> Rng {
+        Rng::from_state(rand::random())
+    }
+
+    // el estado no puede ser 0, xorshift se quedaría en 0 para siempre
+    pub fn from_state(state: u64) -> Rng {
+        Rng {
+            state: if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state },
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new()
+    }
+}
@@ -0,0 +1,180 @@
+use thiserror::Error;
+
+// "CH8S" + versión, después los campos de la máquina en orden fijo
+const MAGIC: &[u8; 4] = b"CH8S";
+const VERSION: u16 = 2;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum StateError {
+    #[error("not a save state")]
+    BadMagic,
+    #[error("unsupported save state version {0}")]
+    UnsupportedVersion(u16),
+    #[error("save state is truncated")]
+    Truncated,
+    #[error("save state has an invalid {0}")]
+    Invalid(&'static str),
+}
+
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> StateWriter {
+        let mut w = StateWriter { data: Vec::new() };
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w
+    }
+
+    pub(crate) fn u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    pub(crate) fn bool(&mut self, val: bool) {
+        self.u8(val.into());
+    }
+
+    pub(crate) fn u16(&mut self, val: u16) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, val: u64) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, val: &[u8]) {
+        self.data.extend_from_slice(val);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        let mut r = StateReader { data };
+
+        if r.bytes(MAGIC.len()).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        match r.u16()? {
+            VERSION => Ok(r),
+            v => Err(StateError::UnsupportedVersion(v)),
+        }
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean")),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    // no tiene que sobrar nada
+    pub(crate) fn finish(self) -> Result<(), StateError> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(StateError::Invalid("length")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use crate::quirks::Quirks;
+    use crate::rng::Rng;
+
+    // dibuja, suma y usa Cxkk en un loop
+    const PROGRAM: &[u8] = &[0x60, 0x05, 0xa2, 0x0a, 0xd0, 0x15, 0xc1, 0xff, 0x12, 0x04, 0xf0];
+
+    fn running() -> Chip8 {
+        let mut c = Chip8::with_rng(Quirks::MODERN, Rng::from_state(7));
+        c.load_rom_bytes(PROGRAM).unwrap();
+        c.key_press(4);
+        for _ in 0..7 {
+            c.step().unwrap();
+        }
+        c
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut c = running();
+        let saved = c.save_state();
+        for _ in 0..9 {
+            c.step().unwrap();
+        }
+        let later = c.save_state();
+
+        c.load_state(&saved).unwrap();
+        assert_eq!(c.save_state(), saved);
+        assert_eq!(c.program_counter(), running().program_counter());
+        assert_eq!(c.registers(), running().registers());
+
+        // incluido el generador de números al azar
+        for _ in 0..9 {
+            c.step().unwrap();
+        }
+        assert_eq!(c.save_state(), later);
+    }
+
+    #[test]
+    fn bad_states_are_rejected() {
+        let mut c = running();
+        let saved = c.save_state();
+
+        let mut bad = saved.clone();
+        bad[0] = b'X';
+        assert_eq!(c.load_state(&bad), Err(StateError::BadMagic));
+        assert_eq!(c.load_state(b"CH"), Err(StateError::BadMagic));
+
+        let mut bad = saved.clone();
+        bad[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(c.load_state(&bad), Err(StateError::UnsupportedVersion(VERSION + 1)));
+
+        assert_eq!(c.load_state(&saved[..saved.len() - 1]), Err(StateError::Truncated));
+        let mut long = saved.clone();
+        long.push(0);
+        assert_eq!(c.load_state(&long), Err(StateError::Invalid("length")));
+
+        // si falla queda como estaba
+        assert_eq!(c.save_state(), saved);
+    }
+}
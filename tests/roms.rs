@@ -0,0 +1,158 @@
+// Corre ROMs de prueba sin ventana y compara la pantalla final con una imagen
+// guardada en tests/golden/<caso>.txt (el formato de headless::ascii).
+//
+// Los .asm de tests/roms se ensamblan acá mismo. Los que usan check.inc
+// marcan cada chequeo con un cuadrado o una cruz contra valores escritos a
+// mano, y esas marcas se comprueban aparte de la imagen, que sólo la
+// escribe el emulador. Si falta la imagen de un caso, o para regenerarlas
+// después de un cambio a propósito:
+//
+//     UPDATE_GOLDEN=1 cargo test --test roms
+
+use chip_8::assembler::assemble_file;
+use chip_8::headless;
+use chip_8::{Chip8, QuirkProfile, Rng};
+use std::path::{Path, PathBuf};
+
+// qué marcas de check.inc tiene que mostrar
+#[derive(Clone, Copy)]
+enum Marks {
+    // no usa check.inc
+    None,
+    AllPass,
+    // quirks.asm: un cuadrado por quirk activa
+    Quirks,
+}
+
+struct Case {
+    name: &'static str,
+    rom: &'static str,
+    profile: QuirkProfile,
+    frames: u32,
+    keys: &'static str,
+    marks: Marks,
+}
+
+const fn case(name: &'static str, rom: &'static str, profile: QuirkProfile) -> Case {
+    Case { name, rom, profile, frames: 600, keys: "", marks: Marks::None }
+}
+
+const fn checked(name: &'static str, rom: &'static str, profile: QuirkProfile) -> Case {
+    Case { marks: Marks::AllPass, ..case(name, rom, profile) }
+}
+
+const fn quirks(name: &'static str, profile: QuirkProfile) -> Case {
+    Case { marks: Marks::Quirks, ..case(name, "quirks.asm", profile) }
+}
+
+const CASES: &[Case] = &[
+    checked("flags", "flags.asm", QuirkProfile::Modern),
+    checked("memory", "memory.asm", QuirkProfile::Modern),
+    quirks("quirks-vip", QuirkProfile::CosmacVip),
+    quirks("quirks-chip48", QuirkProfile::Chip48),
+    quirks("quirks-schip", QuirkProfile::SuperChip),
+    quirks("quirks-xochip", QuirkProfile::XoChip),
+    quirks("quirks-modern", QuirkProfile::Modern),
+    Case { frames: 120, keys: "5:1 20:5 40:A 60:F", ..case("keypad", "keypad.asm", QuirkProfile::Modern) },
+    case("schip", "schip.asm", QuirkProfile::SuperChip),
+    case("xochip", "xochip.asm", QuirkProfile::XoChip),
+];
+
+fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name)
+}
+
+// ensambla el .asm del caso y devuelve dónde quedó la ROM
+fn rom_path(case: &Case) -> PathBuf {
+    let rom = assemble_file(&dir("roms").join(case.rom), 0x200)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.ch8", case.name));
+    std::fs::write(&out, rom).unwrap();
+    out
+}
+
+// Las marcas de check.inc en orden, true para un cuadrado. Son de 4x4,
+// doce por fila, la primera en (1, 1) y cada 5 pixels.
+fn read_marks(screen: &str) -> Vec<bool> {
+    let rows: Vec<&[u8]> = screen.lines().map(str::as_bytes).collect();
+    let mut marks = Vec::new();
+
+    for y in (1..rows.len().saturating_sub(3)).step_by(5) {
+        for x in (1..61).step_by(5) {
+            let cell: Vec<&[u8]> = rows[y..y + 4].iter().map(|row| &row[x..x + 4]).collect();
+            match cell.as_slice() {
+                [b"####", b"#..#", b"#..#", b"####"] => marks.push(true),
+                [b"#..#", b".##.", b".##.", b"#..#"] => marks.push(false),
+                _ => return marks,
+            }
+        }
+    }
+
+    marks
+}
+
+fn expected_marks(case: &Case, found: usize) -> Option<Vec<bool>> {
+    let q = case.profile.quirks();
+    match case.marks {
+        Marks::None => None,
+        Marks::AllPass => Some(vec![true; found.max(1)]),
+        Marks::Quirks => Some(vec![
+            q.logic_resets_vf,
+            q.shift_uses_vy,
+            q.load_store_increments_index,
+            q.jump_uses_vx,
+            q.clip_sprites,
+            q.display_wait,
+        ]),
+    }
+}
+
+fn run(case: &Case, rom: &Path) -> String {
+    let mut chip8 = Chip8::with_rng(case.profile.quirks(), Rng::from_state(0));
+    chip8.load_rom(rom.to_str().unwrap()).unwrap_or_else(|e| panic!("{}: {}", rom.display(), e));
+
+    let keys = headless::parse_keys(case.keys).unwrap();
+    headless::run(&mut chip8, case.frames, &keys)
+        .unwrap_or_else(|e| panic!("{}: {}", case.name, e));
+
+    headless::ascii(&chip8.screen)
+}
+
+#[test]
+fn roms_match_golden_images() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = Vec::new();
+
+    for case in CASES {
+        let screen = run(case, &rom_path(case));
+
+        let marks = read_marks(&screen);
+        if let Some(expected) = expected_marks(case, marks.len()) {
+            if marks != expected {
+                eprintln!("{}: marks {:?}, expected {:?}\n{}", case.name, marks, expected, screen);
+                failed.push(case.name);
+                continue;
+            }
+        }
+        let golden = dir("golden").join(format!("{}.txt", case.name));
+
+        if update {
+            std::fs::write(&golden, &screen).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&golden) {
+            Ok(expected) if expected == screen => {},
+            Ok(expected) => {
+                eprintln!("{}: expected\n{}\ngot\n{}", case.name, expected, screen);
+                failed.push(case.name);
+            },
+            Err(_) => {
+                eprintln!("{}: missing {}, run with UPDATE_GOLDEN=1", case.name, golden.display());
+                failed.push(case.name);
+            },
+        }
+    }
+
+    assert!(failed.is_empty(), "screens don't match: {}", failed.join(", "));
+}
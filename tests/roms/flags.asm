@@ -0,0 +1,168 @@
+; Resultados y VF de las operaciones aritméticas. Todas las marcas tienen
+; que ser cuadrados (perfil modern).
+
+    LD VA, 1
+    LD VB, 1
+
+; 8xy4, sin carry y con carry
+    LD V2, 0x10
+    LD V3, 0x20
+    ADD V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x30
+    CALL check
+    LD V0, V4
+    LD V1, 0
+    CALL check
+
+    LD V2, 0xFF
+    LD V3, 0x02
+    ADD V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x01
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+; 8xy5, sin borrow, con borrow e iguales
+    LD V2, 0x30
+    LD V3, 0x10
+    SUB V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x20
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+    LD V2, 0x10
+    LD V3, 0x30
+    SUB V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0xE0
+    CALL check
+    LD V0, V4
+    LD V1, 0
+    CALL check
+
+    LD V2, 0x42
+    LD V3, 0x42
+    SUB V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+; 8xy7, sin borrow, con borrow e iguales
+    LD V2, 0x10
+    LD V3, 0x30
+    SUBN V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x20
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+    LD V2, 0x30
+    LD V3, 0x10
+    SUBN V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0xE0
+    CALL check
+    LD V0, V4
+    LD V1, 0
+    CALL check
+
+    LD V2, 0x42
+    LD V3, 0x42
+    SUBN V2, V3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+; 8xy6 y 8xyE
+    LD V2, 0x05
+    SHR V2
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+    LD V2, 0x81
+    SHL V2
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 1
+    CALL check
+
+; con VF como destino queda el flag, no el resultado
+    LD VF, 0xFF
+    LD V3, 0x02
+    ADD VF, V3
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD VF, 0x01
+    LD V3, 0x02
+    SUB VF, V3
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+; 8xy1, 8xy2, 8xy3
+    LD V2, 0x0C
+    LD V3, 0x0A
+    OR V2, V3
+    LD V0, V2
+    LD V1, 0x0E
+    CALL check
+
+    LD V2, 0x0C
+    AND V2, V3
+    LD V0, V2
+    LD V1, 0x08
+    CALL check
+
+    LD V2, 0x0C
+    XOR V2, V3
+    LD V0, V2
+    LD V1, 0x06
+    CALL check
+
+; 7xkk da la vuelta sin tocar VF
+    LD VF, 0x55
+    LD V2, 0xFE
+    ADD V2, 3
+    LD V4, VF
+    LD V0, V2
+    LD V1, 0x01
+    CALL check
+    LD V0, V4
+    LD V1, 0x55
+    CALL check
+
+end: JP end
+
+INCLUDE "check.inc"
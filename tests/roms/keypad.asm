@@ -0,0 +1,16 @@
+; Una marca en x = 4 * tecla por cada tecla que se aprieta y se suelta.
+
+loop:
+    LD V0, K
+    LD V1, V0
+    SHL V1
+    SHL V1
+    LD V2, 10
+    LD I, mark
+    DRW V1, V2, 3
+release:
+    SKNP V0
+    JP release
+    JP loop
+
+mark: DB 0xE0, 0xE0, 0xE0
@@ -0,0 +1,93 @@
+; BCD, carga y guardado de registros e index. Todas las marcas tienen que
+; ser cuadrados (perfil modern).
+
+    LD VA, 1
+    LD VB, 1
+
+; Fx33
+    LD V2, 254
+    LD I, scratch
+    LD B, V2
+    LD V2, [I]
+    LD V3, V0
+    LD V4, V1
+    LD V5, V2
+    LD V0, V3
+    LD V1, 2
+    CALL check
+    LD V0, V4
+    LD V1, 5
+    CALL check
+    LD V0, V5
+    LD V1, 4
+    CALL check
+
+; Fx55 y Fx65 no mueven index
+    LD V0, 0x11
+    LD V1, 0x22
+    LD V2, 0x33
+    LD I, scratch
+    LD [I], V2
+    LD V0, 0
+    LD V1, 0
+    LD V2, 0
+    LD V1, [I]
+    LD V3, V0
+    LD V4, V1
+    LD V5, V2
+    LD V0, V3
+    LD V1, 0x11
+    CALL check
+    LD V0, V4
+    LD V1, 0x22
+    CALL check
+    ; V2 no se cargó
+    LD V0, V5
+    LD V1, 0
+    CALL check
+    ; el dato sigue en scratch
+    LD I, scratch
+    LD V0, [I]
+    LD V1, 0x11
+    CALL check
+
+; Fx1E
+    LD I, table
+    LD V2, 3
+    ADD I, V2
+    LD V0, [I]
+    LD V1, 0x44
+    CALL check
+
+; 5xy2 y 5xy3 (XO-CHIP), al revés si X > Y
+    LD V2, 0x01
+    LD V3, 0x02
+    LD V4, 0x03
+    LD I, scratch
+    SAVE V4, V2
+    LD V0, [I]
+    LD V1, 0x03
+    CALL check
+    LD I, scratch
+    LOAD V5, V7
+    LD V0, V7
+    LD V1, 0x01
+    CALL check
+
+; Fx75 y Fx85
+    LD V0, 0x77
+    LD V1, 0x88
+    LD R, V1
+    LD V0, 0
+    LD V1, 0
+    LD V1, R
+    LD V0, V1
+    LD V1, 0x88
+    CALL check
+
+end: JP end
+
+INCLUDE "check.inc"
+
+table: DB 0x11, 0x22, 0x33, 0x44
+scratch: DB 0, 0, 0, 0
@@ -0,0 +1,89 @@
+; Qué quirks están activas: cuadrado si lo están, cruz si no. En orden:
+; VF en 8xy1, 8xy6 usa VY, Fx65 mueve index, Bnnn usa VX, sprites cortados
+; en el borde y un sprite por frame.
+
+    LD VA, 1
+    LD VB, 1
+    LD V1, 1
+
+; logic_resets_vf
+    LD VF, 5
+    OR V2, V3
+    LD V0, 0
+    SNE VF, 0
+    LD V0, 1
+    CALL check
+
+; shift_uses_vy
+    LD V2, 0
+    LD V3, 2
+    SHR V2, V3
+    LD V0, V2
+    CALL check
+
+; load_store_increments_index: la segunda lectura ve el byte siguiente
+    LD I, index
+    LD V0, [I]
+    LD V0, [I]
+    CALL check
+
+; jump_uses_vx: jumps está en 0x2nn, así que con la quirk suma V2
+    LD V0, 0
+    LD V2, 2
+    JP V0, jumps
+jumps:
+    JP no_vx
+    JP vx
+no_vx:
+    LD V0, 0
+    JP jumped
+vx:
+    LD V0, 1
+jumped:
+    CALL check
+
+; clip_sprites: dos pixeles en x = 63, el segundo da la vuelta o no
+    LD I, two
+    LD V2, 63
+    LD V3, 0
+    DRW V2, V3, 1
+    LD I, one
+    LD V2, 0
+    DRW V2, V3, 1
+    LD V4, VF
+    DRW V2, V3, 1
+    LD I, two
+    LD V2, 63
+    DRW V2, V3, 1
+    LD V0, 1
+    SE V4, 0
+    LD V0, 0
+    CALL check
+
+; display_wait: 20 sprites tardan 20 frames si hay que esperar al vblank,
+; y unos 10 si no (8 instrucciones por frame)
+    LD V5, 60
+    LD DT, V5
+    LD I, one
+    LD V2, 0
+    LD V8, 20
+draws:
+    DRW V2, V3, 1
+    ADD V8, 255
+    SE V8, 0
+    JP draws
+    LD V6, DT
+    LD V7, 45
+    SUBN V7, V6
+    LD V0, 1
+    SE VF, 0
+    LD V0, 0
+    CALL check
+
+end: JP end
+
+INCLUDE "check.inc"
+
+index: DB 0, 1
+one: DB 0x80
+two: DB 0xC0
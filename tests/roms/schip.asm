@@ -0,0 +1,24 @@
+; Alta resolución, sprites de 16x16 y scroll de SUPER-CHIP.
+
+    HIGH
+    LD I, big
+    LD V0, 0
+    LD V1, 0
+    DRW V0, V1, 0
+    LD V0, 120
+    LD V1, 56
+    DRW V0, V1, 0
+    SCD 4
+    SCR
+    LD I, small
+    LD V0, 60
+    LD V1, 30
+    DRW V0, V1, 4
+    SCL
+    SCU 2
+end: JP end
+
+big:
+    DW 0xFFFF, 0x8001, 0x8001, 0x8001, 0x8FF1, 0x8811, 0x8811, 0x8811
+    DW 0x8811, 0x8811, 0x8811, 0x8FF1, 0x8001, 0x8001, 0x8001, 0xFFFF
+small: DB 0xF0, 0x90, 0x90, 0xF0
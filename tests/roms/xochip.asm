@@ -0,0 +1,21 @@
+; Planos de XO-CHIP: un sprite en cada plano y uno en los dos, que lleva
+; los datos de cada plano uno atrás del otro.
+
+    LD V1, 4
+    PLANE 1
+    LD I, LONG bar
+    LD V0, 4
+    DRW V0, V1, 4
+    PLANE 2
+    LD V0, 12
+    DRW V0, V1, 4
+    PLANE 3
+    LD I, both
+    LD V0, 20
+    DRW V0, V1, 4
+end: JP end
+
+bar: DB 0xFF, 0xFF, 0xFF, 0xFF
+both:
+    DB 0xF0, 0xF0, 0xF0, 0xF0
+    DB 0x3C, 0x3C, 0x3C, 0x3C